@@ -0,0 +1,175 @@
+use crate::metadata::MetadataValue;
+use crate::options::{Colormap, NamedColormap};
+use crate::raw::to_8bit;
+use crate::typ::DecodedImage;
+use anyhow::Result;
+use png::{BitDepth, ColorType};
+
+/// A colormap as stops of (position from 0 to 1, color), interpolated linearly.
+type Stops = &'static [(f32, [u8; 3])];
+
+/// Evenly spaced stops, as the colors are sampled in the reference implementations.
+macro_rules! even_stops {
+    ($($color:expr),* $(,)?) => {{
+        const COLORS: &[[u8; 3]] = &[$($color),*];
+        const STOPS: [(f32, [u8; 3]); COLORS.len()] = {
+            let mut stops = [(0.0, [0; 3]); COLORS.len()];
+            let mut i = 0;
+            while i < COLORS.len() {
+                stops[i] = (i as f32 / (COLORS.len() - 1) as f32, COLORS[i]);
+                i += 1;
+            }
+            stops
+        };
+        &STOPS
+    }};
+}
+
+fn stops(colormap: NamedColormap) -> Stops {
+    match colormap {
+        NamedColormap::Viridis => even_stops![
+            [0x44, 0x01, 0x54],
+            [0x48, 0x28, 0x78],
+            [0x3e, 0x4a, 0x89],
+            [0x31, 0x68, 0x8e],
+            [0x26, 0x82, 0x8e],
+            [0x1f, 0x9e, 0x89],
+            [0x35, 0xb7, 0x79],
+            [0x6d, 0xcd, 0x59],
+            [0xb4, 0xde, 0x2c],
+            [0xfd, 0xe7, 0x25],
+        ],
+        NamedColormap::Magma => even_stops![
+            [0x00, 0x00, 0x04],
+            [0x18, 0x0f, 0x3e],
+            [0x45, 0x10, 0x77],
+            [0x72, 0x1f, 0x81],
+            [0x9f, 0x2f, 0x7f],
+            [0xcd, 0x40, 0x71],
+            [0xf1, 0x60, 0x5d],
+            [0xfd, 0x95, 0x67],
+            [0xfe, 0xc9, 0x8d],
+            [0xfc, 0xfd, 0xbf],
+        ],
+        NamedColormap::Inferno => even_stops![
+            [0x00, 0x00, 0x04],
+            [0x1b, 0x0c, 0x42],
+            [0x4b, 0x0c, 0x6b],
+            [0x78, 0x1c, 0x6d],
+            [0xa5, 0x2c, 0x60],
+            [0xcf, 0x44, 0x46],
+            [0xed, 0x69, 0x25],
+            [0xfb, 0x9a, 0x06],
+            [0xf7, 0xd0, 0x3c],
+            [0xfc, 0xff, 0xa4],
+        ],
+        NamedColormap::Cividis => even_stops![
+            [0x00, 0x20, 0x4d],
+            [0x00, 0x33, 0x6f],
+            [0x39, 0x48, 0x6b],
+            [0x57, 0x5c, 0x6d],
+            [0x70, 0x71, 0x73],
+            [0x8a, 0x87, 0x79],
+            [0xa6, 0x9d, 0x75],
+            [0xc4, 0xb5, 0x6c],
+            [0xe4, 0xcf, 0x5b],
+            [0xff, 0xea, 0x46],
+        ],
+        // As in matplotlib: red, then green, then blue ramp up.
+        NamedColormap::Hot => &[
+            (0.0, [10, 0, 0]),
+            (0.365, [255, 0, 0]),
+            (0.746, [255, 255, 0]),
+            (1.0, [255, 255, 255]),
+        ],
+        // After ImageJ's "Fire".
+        NamedColormap::Fire => &[
+            (0.0, [0, 0, 0]),
+            (0.12, [0, 0, 130]),
+            (0.25, [70, 0, 220]),
+            (0.37, [150, 0, 160]),
+            (0.5, [210, 20, 60]),
+            (0.62, [245, 80, 0]),
+            (0.75, [255, 150, 0]),
+            (0.87, [255, 220, 30]),
+            (1.0, [255, 255, 255]),
+        ],
+        // ColorBrewer RdBu, from red (low) through white to blue (high).
+        NamedColormap::RdBu => even_stops![
+            [0x67, 0x00, 0x1f],
+            [0xb2, 0x18, 0x2b],
+            [0xd6, 0x60, 0x4d],
+            [0xf4, 0xa5, 0x82],
+            [0xfd, 0xdb, 0xc7],
+            [0xf7, 0xf7, 0xf7],
+            [0xd1, 0xe5, 0xf0],
+            [0x92, 0xc5, 0xde],
+            [0x43, 0x93, 0xc3],
+            [0x21, 0x66, 0xac],
+            [0x05, 0x30, 0x61],
+        ],
+    }
+}
+
+/// A 256-entry lookup table for `colormap`.
+fn lookup_table(colormap: &Colormap) -> Result<[[u8; 3]; 256]> {
+    let custom: Vec<(f32, [u8; 3])>;
+    let stops = match colormap {
+        Colormap::Named(name) => stops(*name),
+        Colormap::Custom(colors) => {
+            if colors.len() < 2 {
+                anyhow::bail!("A custom colormap needs at least 2 colors");
+            }
+            let last = (colors.len() - 1) as f32;
+            custom = colors
+                .iter()
+                .enumerate()
+                .map(|(i, &color)| (i as f32 / last, color))
+                .collect();
+            &custom
+        }
+    };
+    Ok(std::array::from_fn(|i| {
+        let position = i as f32 / 255.0;
+        let upper = stops
+            .iter()
+            .position(|&(stop, _)| stop >= position)
+            .unwrap_or(stops.len() - 1)
+            .max(1);
+        let ((low, from), (high, to)) = (stops[upper - 1], stops[upper]);
+        let t = ((position - low) / (high - low)).clamp(0.0, 1.0);
+        std::array::from_fn(|c| {
+            (from[c] as f32 + (to[c] as f32 - from[c] as f32) * t).round() as u8
+        })
+    }))
+}
+
+/// Map a single-channel image through `colormap` to 8-bit RGB (or RGBA, keeping alpha).
+/// Other images are returned as they are.
+pub fn apply(mut image: DecodedImage, colormap: &Colormap) -> Result<DecodedImage> {
+    let (channels, color_type) = match image.color_type {
+        ColorType::Grayscale => (1, ColorType::Rgb),
+        ColorType::GrayscaleAlpha => (2, ColorType::Rgba),
+        _ => return Ok(image),
+    };
+    let lut = lookup_table(colormap)?;
+    let values = to_8bit(&image)?.into_owned();
+    let mut data = Vec::with_capacity(values.len() / channels * (channels + 2));
+    for pixel in values.chunks_exact(channels) {
+        data.extend_from_slice(&lut[pixel[0] as usize]);
+        if channels == 2 {
+            data.push(pixel[1]);
+        }
+    }
+    image.data = data;
+    image.color_type = color_type;
+    image.depth = BitDepth::Eight;
+    image.info.bit_depth = 8;
+    if let Some(metadata) = image.info.metadata.as_mut() {
+        metadata.insert(
+            "colormap".to_string(),
+            MetadataValue::String(colormap.name().to_string()),
+        );
+    }
+    Ok(image)
+}
@@ -0,0 +1,123 @@
+use crate::metadata::{MetadataMap, MetadataValue};
+use crate::options::DecodeOptions;
+use crate::typ::{DecodeResult, ProbeResult};
+use anyhow::{Result, anyhow};
+use std::borrow::Cow;
+use std::io::Read;
+
+/// Compression formats input files may be wrapped in (e.g. EMDB's `.map.gz`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect a compression wrapper from the magic bytes at the start of `data`.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0x1F, 0x8B]) {
+            Some(Compression::Gzip)
+        } else if data.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if data.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Bzip2 => "bzip2",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// Decompress `data` if it is wrapped in a supported compression format,
+/// refusing to inflate to more than `max_size` bytes.
+pub fn decompress(data: &[u8], max_size: usize) -> Result<(Cow<'_, [u8]>, Option<Compression>)> {
+    let Some(compression) = Compression::detect(data) else {
+        return Ok((Cow::Borrowed(data), None));
+    };
+    let reader: Box<dyn Read + '_> = match compression {
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(data)),
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(data)),
+        Compression::Zstd => Box::new(
+            ruzstd::decoding::StreamingDecoder::new(data)
+                .map_err(|e| anyhow!("Invalid zstd data: {e}"))?,
+        ),
+    };
+    let mut decompressed = Vec::new();
+    reader
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| anyhow!("Failed to decompress {} data: {e}", compression.name()))?;
+    if decompressed.len() > max_size {
+        anyhow::bail!(
+            "Decompressed {} data exceeds the limit of {max_size} bytes",
+            compression.name()
+        );
+    }
+    Ok((Cow::Owned(decompressed), Some(compression)))
+}
+
+/// Decompress `data` if needed, then decode it with `decode`,
+/// recording any compression wrapper in the file metadata.
+pub fn decode_wrapped(
+    data: &[u8],
+    options: &DecodeOptions,
+    decode: impl FnOnce(&[u8], &DecodeOptions) -> Result<DecodeResult>,
+) -> Result<DecodeResult> {
+    let (unwrapped, compression) = decompress(data, options.max_decompressed_size)?;
+    let mut result = decode(&unwrapped, options)?;
+    if let Some(compression) = compression {
+        add_metadata(
+            &mut result.metadata,
+            compression,
+            data.len(),
+            unwrapped.len(),
+        );
+    }
+    Ok(result)
+}
+
+/// Like [`decode_wrapped`], for reading image information only.
+pub fn read_info_wrapped(
+    data: &[u8],
+    options: &DecodeOptions,
+    read_info: impl FnOnce(&[u8], &DecodeOptions) -> Result<ProbeResult>,
+) -> Result<ProbeResult> {
+    let (unwrapped, compression) = decompress(data, options.max_decompressed_size)?;
+    let mut result = read_info(&unwrapped, options)?;
+    if let Some(compression) = compression {
+        add_metadata(
+            &mut result.metadata,
+            compression,
+            data.len(),
+            unwrapped.len(),
+        );
+    }
+    Ok(result)
+}
+
+/// Record the compression wrapper and the sizes before and after decompression.
+pub fn add_metadata(
+    metadata: &mut Option<MetadataMap>,
+    compression: Compression,
+    compressed_size: usize,
+    decompressed_size: usize,
+) {
+    let metadata = metadata.get_or_insert_with(Default::default);
+    metadata.insert("compression".to_string(), compression.name().into());
+    metadata.insert(
+        "compressed_size".to_string(),
+        MetadataValue::from(compressed_size),
+    );
+    metadata.insert(
+        "decompressed_size".to_string(),
+        MetadataValue::from(decompressed_size),
+    );
+}
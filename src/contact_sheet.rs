@@ -0,0 +1,164 @@
+use crate::metadata::{MetadataMap, MetadataValue};
+use crate::mrc::montage_grid;
+use crate::options::{ContactSheetOptions, DecodeOptions, MetadataLevel};
+use crate::raw::to_rgba8;
+use crate::resize::{fit_within, resize};
+use crate::typ::{DecodeResult, DecodedImage, ImageInfo};
+use anyhow::{Result, anyhow};
+use png::{BitDepth, ColorType};
+
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+
+/// Digits 0-9 in a 3x5 pixel font, row by row from the top left, one bit per pixel.
+const DIGITS: [u16; 10] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_001_001_001,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+];
+
+/// Replace the decoded images with a single contact sheet of all of them.
+pub fn apply(
+    res: DecodeResult,
+    sheet: &ContactSheetOptions,
+    options: &DecodeOptions,
+) -> Result<DecodeResult> {
+    if res.images.is_empty() {
+        return Ok(res);
+    }
+    let sheet = contact_sheet(res.images, sheet, options)?;
+    Ok(DecodeResult {
+        images: vec![sheet],
+        ..res
+    })
+}
+
+/// Tile downscaled copies of `images` into a grid, in order, with each image centered
+/// in its cell. The tile map (`tile_<image index>` as `"x,y,width,height"`) is returned
+/// in the metadata. The sheet is subject to the size limits of `decode_options`.
+pub fn contact_sheet(
+    images: Vec<DecodedImage>,
+    options: &ContactSheetOptions,
+    decode_options: &DecodeOptions,
+) -> Result<DecodedImage> {
+    let (columns, rows) = montage_grid(images.len(), options.columns);
+    let cell = options.tile_size.max(1) as usize;
+    let spacing = options.spacing as usize;
+    let size = |count: usize| {
+        count
+            .checked_mul(cell)
+            .zip((count + 1).checked_mul(spacing))
+            .and_then(|(tiles, gaps)| tiles.checked_add(gaps))
+            .and_then(|size| u32::try_from(size).ok())
+            .ok_or_else(|| anyhow!("Contact sheet size overflows"))
+    };
+    let (width_u32, height_u32) = (size(columns)?, size(rows)?);
+    decode_options.check_dimensions(width_u32, height_u32)?;
+    let (width, height) = (width_u32 as usize, height_u32 as usize);
+    let pixels = width
+        .checked_mul(height)
+        .filter(|pixels| pixels.checked_mul(BACKGROUND.len()).is_some())
+        .ok_or_else(|| anyhow!("Contact sheet size overflows"))?;
+
+    let mut data = BACKGROUND.repeat(pixels);
+    let mut metadata = MetadataMap::from([
+        ("stack_mode".to_string(), "contact_sheet".into()),
+        ("montage_columns".to_string(), columns.into()),
+        ("montage_rows".to_string(), rows.into()),
+        ("tile_count".to_string(), images.len().into()),
+    ]);
+    for (n, image) in images.into_iter().enumerate() {
+        let index = image.info.image_index;
+        let (tile_width, tile_height) = fit_within(
+            image.width,
+            image.height,
+            Some(cell as u32),
+            Some(cell as u32),
+        );
+        let tile = if (tile_width, tile_height) == (image.width, image.height) {
+            image
+        } else {
+            resize(image, tile_width, tile_height, options.filter)?
+        };
+        let rgba = to_rgba8(&tile)?;
+
+        let cell_x = spacing + (n % columns) * (cell + spacing);
+        let cell_y = spacing + (n / columns) * (cell + spacing);
+        let x = cell_x + (cell - tile_width as usize) / 2;
+        let y = cell_y + (cell - tile_height as usize) / 2;
+        for (row, pixels) in rgba.chunks_exact(tile_width as usize * 4).enumerate() {
+            let start = ((y + row) * width + x) * 3;
+            for (out, pixel) in data[start..start + tile_width as usize * 3]
+                .chunks_exact_mut(3)
+                .zip(pixels.chunks_exact(4))
+            {
+                // Composite over the background.
+                let alpha = pixel[3] as u32;
+                for c in 0..3 {
+                    out[c] = ((pixel[c] as u32 * alpha + out[c] as u32 * (255 - alpha) + 127) / 255)
+                        as u8;
+                }
+            }
+        }
+        if options.labels {
+            draw_label(&mut data, width, (cell_x, cell_y), cell, index);
+        }
+        metadata.insert(
+            format!("tile_{index}"),
+            MetadataValue::String(format!("{x},{y},{tile_width},{tile_height}")),
+        );
+    }
+
+    Ok(DecodedImage {
+        width: width_u32,
+        height: height_u32,
+        color_type: ColorType::Rgb,
+        depth: BitDepth::Eight,
+        data,
+        info: ImageInfo {
+            image_index: 0,
+            width: width_u32,
+            height: height_u32,
+            color_type: "RGB".to_string(),
+            bit_depth: 8,
+            metadata: (decode_options.metadata != MetadataLevel::None).then_some(metadata),
+        },
+        samples: None,
+        icc_profile: None,
+    })
+}
+
+/// Draw `number` in white on black in the corner of the `cell` x `cell` square at
+/// (`x`, `y`) of an RGB image `width` pixels wide, clipped to that square.
+fn draw_label(data: &mut [u8], width: usize, (x, y): (usize, usize), cell: usize, number: usize) {
+    const SCALE: usize = 2;
+    let digits: Vec<usize> = number
+        .to_string()
+        .bytes()
+        .map(|b| (b - b'0') as usize)
+        .collect();
+    // One pixel of padding around the digits, which are 3 wide with 1 between them.
+    let box_width = (digits.len() * 4 + 1) * SCALE;
+    let box_height = 7 * SCALE;
+    for by in 0..box_height.min(cell) {
+        for bx in 0..box_width.min(cell) {
+            let (px, py) = (x + bx, y + by);
+            let (gx, gy) = (bx / SCALE, by / SCALE);
+            let lit = (1..6).contains(&gy)
+                && gx >= 1
+                && (gx - 1) % 4 < 3
+                && digits.get((gx - 1) / 4).is_some_and(|&digit| {
+                    let bit = (gy - 1) * 3 + (gx - 1) % 4;
+                    DIGITS[digit] & (1 << (14 - bit)) != 0
+                });
+            let value = if lit { 255 } else { 0 };
+            data[(py * width + px) * 3..][..3].fill(value);
+        }
+    }
+}
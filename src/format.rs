@@ -0,0 +1,81 @@
+use crate::options::DecodeOptions;
+use crate::typ::{DecodeResult, ProbeResult};
+use crate::{mrc, tiff};
+use anyhow::Result;
+use serde::Serialize;
+
+/// An image format that can be recognised and decoded.
+///
+/// To add a format, implement this trait and list the decoder in [`FORMATS`].
+pub trait FormatDecoder: Sync {
+    /// Short identifier, e.g. `"tiff"`; recorded as `format` in the file metadata.
+    fn name(&self) -> &'static str;
+    /// File extensions commonly used for the format, without the leading dot.
+    fn extensions(&self) -> &'static [&'static str];
+    fn mime_types(&self) -> &'static [&'static str];
+    /// Whether `data` looks like this format (by magic bytes or header heuristics).
+    fn probe(&self, data: &[u8]) -> bool;
+    fn decode(&self, data: &[u8], options: &DecodeOptions) -> Result<DecodeResult>;
+    /// Read image dimensions, types and metadata from the headers only, without decoding pixels.
+    fn read_info(&self, data: &[u8], options: &DecodeOptions) -> Result<ProbeResult>;
+}
+
+/// All supported formats, in the order they are probed.
+pub static FORMATS: &[&dyn FormatDecoder] = &[&tiff::TiffFormat, &mrc::MrcFormat];
+
+pub fn find_format(name: &str) -> Option<&'static dyn FormatDecoder> {
+    FORMATS
+        .iter()
+        .copied()
+        .find(|format| format.name().eq_ignore_ascii_case(name))
+}
+
+pub fn detect_format(data: &[u8]) -> Option<&'static dyn FormatDecoder> {
+    FORMATS.iter().copied().find(|format| format.probe(data))
+}
+
+/// Decode `data` with `format`, recording the format name in the file metadata.
+pub fn decode_as(
+    format: &dyn FormatDecoder,
+    data: &[u8],
+    options: &DecodeOptions,
+) -> Result<DecodeResult> {
+    let mut result = format.decode(data, options)?;
+    result
+        .metadata
+        .get_or_insert_with(Default::default)
+        .insert("format".to_string(), format.name().into());
+    Ok(result)
+}
+
+/// Like [`decode_as`], but only reading image information.
+pub fn read_info_as(
+    format: &dyn FormatDecoder,
+    data: &[u8],
+    options: &DecodeOptions,
+) -> Result<ProbeResult> {
+    let mut result = format.read_info(data, options)?;
+    result
+        .metadata
+        .get_or_insert_with(Default::default)
+        .insert("format".to_string(), format.name().into());
+    Ok(result)
+}
+
+#[derive(Serialize)]
+pub struct FormatInfo {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub mime_types: &'static [&'static str],
+}
+
+pub fn supported_formats() -> Vec<FormatInfo> {
+    FORMATS
+        .iter()
+        .map(|format| FormatInfo {
+            name: format.name(),
+            extensions: format.extensions(),
+            mime_types: format.mime_types(),
+        })
+        .collect()
+}
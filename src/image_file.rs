@@ -0,0 +1,201 @@
+use crate::format::{self, FormatDecoder};
+use crate::mrc::{MrcFormat, StackMode};
+use crate::options::{DecodeOptions, FrameSelection, MetadataLevel};
+use crate::typ::{DecodedImage, ProbeResult};
+use crate::{compression, encode_image, image_to_js, js_options, utils};
+use anyhow::Result;
+use std::cell::OnceCell;
+use wasm_bindgen::prelude::*;
+
+/// An opened input file whose pages (TIFF pages, MRC slices) are decoded on demand.
+///
+/// Opening a file decompresses it and reads the page information from the headers;
+/// pixel data is only decoded by [`ImageFile::decode_page`].
+#[wasm_bindgen]
+pub struct ImageFile {
+    data: Vec<u8>,
+    format: &'static dyn FormatDecoder,
+    options: DecodeOptions,
+    info: ProbeResult,
+    /// Image index of each page, in order.
+    pages: Vec<usize>,
+    /// The single page of a composite MRC stack mode, once decoded.
+    composite: OnceCell<DecodedImage>,
+}
+
+impl ImageFile {
+    /// Open `data` as `format`, or as whatever format is detected if `None`.
+    pub fn open(
+        data: &[u8],
+        format: Option<&'static dyn FormatDecoder>,
+        options: DecodeOptions,
+    ) -> Result<Self> {
+        let (unwrapped, compression) =
+            compression::decompress(data, options.max_decompressed_size)?;
+        let format = match format {
+            Some(format) => format,
+            None => crate::detect(&unwrapped)?,
+        };
+        let mut info = format::read_info_as(format, &unwrapped, &options)?;
+        if let Some(compression) = compression {
+            compression::add_metadata(&mut info.metadata, compression, data.len(), unwrapped.len());
+        }
+        if options.metadata == MetadataLevel::None {
+            info.strip_metadata();
+        }
+
+        // Pages that can't be read are still pages; decoding them reports the error.
+        let mut pages: Vec<usize> = info
+            .images
+            .iter()
+            .map(|image| image.image_index)
+            .chain(info.errors.iter().map(|error| error.image_index))
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+
+        Ok(ImageFile {
+            data: unwrapped.into_owned(),
+            format,
+            options,
+            info,
+            pages,
+            composite: OnceCell::new(),
+        })
+    }
+
+    pub fn format(&self) -> &'static dyn FormatDecoder {
+        self.format
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Information on all pages, as read when opening the file.
+    pub fn info(&self) -> &ProbeResult {
+        &self.info
+    }
+
+    /// Decode page `index` (counting from 0 up to [`ImageFile::page_count`]).
+    pub fn decode_page(&self, index: usize) -> Result<DecodedImage> {
+        let Some(&image_index) = self.pages.get(index) else {
+            anyhow::bail!(
+                "Page {index} out of range, the file has {} pages",
+                self.pages.len()
+            );
+        };
+        // Composite MRC stack modes combine all selected frames into a single page,
+        // which is decoded once. Otherwise pages map to frames, so only the page
+        // itself is decoded.
+        if self.format.name() == MrcFormat.name()
+            && !matches!(self.options.stack, StackMode::Slices)
+        {
+            if let Some(image) = self.composite.get() {
+                return Ok(image.clone());
+            }
+            let image = self.decode_image(index, image_index, &self.options)?;
+            return Ok(self.composite.get_or_init(|| image).clone());
+        }
+        let options = DecodeOptions {
+            frames: FrameSelection {
+                start: image_index,
+                end: Some(image_index + 1),
+                step: 1,
+            },
+            ..self.options.clone()
+        };
+        self.decode_image(index, image_index, &options)
+    }
+
+    /// Decode the file with `options` and pick out page `index` (image `image_index`).
+    fn decode_image(
+        &self,
+        index: usize,
+        image_index: usize,
+        options: &DecodeOptions,
+    ) -> Result<DecodedImage> {
+        let result = self.format.decode(&self.data, options)?;
+        if let Some(error) = result
+            .errors
+            .into_iter()
+            .find(|error| error.image_index == image_index)
+        {
+            anyhow::bail!(error.message);
+        }
+        let mut image = result
+            .images
+            .into_iter()
+            .find(|image| image.info.image_index == image_index)
+            .ok_or_else(|| anyhow::anyhow!("Page {index} could not be decoded"))?;
+        if self.options.metadata == MetadataLevel::None {
+            image.info.metadata = None;
+        }
+        Ok(image)
+    }
+}
+
+#[wasm_bindgen]
+impl ImageFile {
+    #[wasm_bindgen(constructor)]
+    pub fn js_new(
+        data: &[u8],
+        #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+    ) -> std::result::Result<ImageFile, JsValue> {
+        utils::set_panic_hook();
+        js_options(options)
+            .and_then(|options| ImageFile::open(data, None, options))
+            .map_err(|e| JsValue::from_str(&format!("{e}")))
+    }
+
+    #[wasm_bindgen(js_name = "openAs")]
+    pub fn js_open_as(
+        format: &str,
+        data: &[u8],
+        #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+    ) -> std::result::Result<ImageFile, JsValue> {
+        utils::set_panic_hook();
+        let format = format::find_format(format)
+            .ok_or_else(|| JsValue::from_str(&format!("Unsupported format: {format}")))?;
+        js_options(options)
+            .and_then(|options| ImageFile::open(data, Some(format), options))
+            .map_err(|e| JsValue::from_str(&format!("{e}")))
+    }
+
+    #[wasm_bindgen(getter, js_name = "format")]
+    pub fn js_format(&self) -> String {
+        self.format.name().to_string()
+    }
+
+    #[wasm_bindgen(getter, js_name = "pageCount")]
+    pub fn js_page_count(&self) -> usize {
+        self.page_count()
+    }
+
+    #[wasm_bindgen(getter, js_name = "info", unchecked_return_type = "ProbeOutput")]
+    pub fn js_info(&self) -> std::result::Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.info)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize info: {e}")))
+    }
+
+    /// Information on page `index`, or `undefined` if the page can't be read.
+    #[wasm_bindgen(js_name = "pageInfo", unchecked_return_type = "ImageInfo | undefined")]
+    pub fn js_page_info(&self, index: usize) -> std::result::Result<JsValue, JsValue> {
+        let info = self.pages.get(index).and_then(|&image_index| {
+            self.info
+                .images
+                .iter()
+                .find(|image| image.image_index == image_index)
+        });
+        serde_wasm_bindgen::to_value(&info)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize info: {e}")))
+    }
+
+    #[wasm_bindgen(js_name = "page", unchecked_return_type = "Image")]
+    pub fn js_page(&self, index: usize) -> std::result::Result<JsValue, JsValue> {
+        self.decode_page(index)
+            .and_then(|decoded| encode_image(decoded, &self.options, self.info.metadata.as_ref()))
+            .and_then(|image| image_to_js(&image))
+            .map_err(|e| JsValue::from_str(&format!("{e}")))
+    }
+}
@@ -0,0 +1,320 @@
+use crate::metadata::{MetadataMap, MetadataValue};
+use serde::Deserialize;
+
+/// Number of grey levels CLAHE works with (the output is 8-bit anyway).
+const CLAHE_LEVELS: usize = 256;
+
+/// Percentile window applied before CLAHE quantization, so a handful of outliers
+/// don't squash the rest of the data into a couple of histogram bins.
+const CLAHE_WINDOW: (f32, f32) = (0.5, 99.5);
+
+/// How single-channel sample values are mapped to 8-bit display intensities.
+///
+/// Deserialized from e.g. `{ "mode": "percentile", "low": 1, "high": 99 }`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum IntensityMapping {
+    /// Linear mapping of the data minimum/maximum to 0/255.
+    MinMax,
+    /// Linear mapping of a fixed `min`..`max` window to 0/255,
+    /// clipping values outside of it.
+    Window { min: f32, max: f32 },
+    /// Linear mapping of the given low/high percentiles (0-100) to 0/255,
+    /// clipping values outside of that window.
+    Percentile {
+        #[serde(default = "default_percentile_low")]
+        low: f32,
+        #[serde(default = "default_percentile_high")]
+        high: f32,
+    },
+    /// Global histogram equalization.
+    Equalize,
+    /// Contrast-limited adaptive histogram equalization over a grid of
+    /// `tiles` x `tiles` tiles, with `clip_limit` given as a multiple of the
+    /// mean histogram bin count.
+    Clahe {
+        #[serde(default = "default_clahe_tiles")]
+        tiles: u32,
+        #[serde(default = "default_clahe_clip_limit")]
+        clip_limit: f32,
+    },
+}
+
+fn default_percentile_low() -> f32 {
+    0.5
+}
+
+fn default_percentile_high() -> f32 {
+    99.5
+}
+
+fn default_clahe_tiles() -> u32 {
+    8
+}
+
+fn default_clahe_clip_limit() -> f32 {
+    2.0
+}
+
+impl IntensityMapping {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntensityMapping::MinMax => "min_max",
+            IntensityMapping::Window { .. } => "window",
+            IntensityMapping::Percentile { .. } => "percentile",
+            IntensityMapping::Equalize => "equalize",
+            IntensityMapping::Clahe { .. } => "clahe",
+        }
+    }
+}
+
+pub struct MappedIntensity {
+    pub data: Vec<u8>,
+    /// Minimum and maximum of the finite input values.
+    pub data_range: (f32, f32),
+    /// The input values mapped to 0 and 255 respectively.
+    pub window: (f32, f32),
+}
+
+impl MappedIntensity {
+    /// Record how the data was mapped in an image metadata map.
+    pub fn add_metadata(&self, mapping: &IntensityMapping, metadata: &mut MetadataMap) {
+        metadata.insert("intensity_mapping".to_string(), mapping.name().into());
+        metadata.insert("window_min".to_string(), MetadataValue::from(self.window.0));
+        metadata.insert("window_max".to_string(), MetadataValue::from(self.window.1));
+    }
+}
+
+/// Map `values` (a `width` x `height` image in row-major order) to 8-bit intensities.
+///
+/// Non-finite values are mapped to 0.
+pub fn map_to_u8(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    mapping: &IntensityMapping,
+) -> MappedIntensity {
+    let data_range = finite_range(values);
+    match *mapping {
+        IntensityMapping::MinMax => MappedIntensity {
+            data: linear(values, data_range),
+            data_range,
+            window: data_range,
+        },
+        IntensityMapping::Window { min, max } => MappedIntensity {
+            data: linear(values, (min, max)),
+            data_range,
+            window: (min, max),
+        },
+        IntensityMapping::Percentile { low, high } => {
+            let window = percentile_window(values, low, high);
+            MappedIntensity {
+                data: linear(values, window),
+                data_range,
+                window,
+            }
+        }
+        IntensityMapping::Equalize => MappedIntensity {
+            data: equalize(values),
+            data_range,
+            window: data_range,
+        },
+        IntensityMapping::Clahe { tiles, clip_limit } => {
+            let window = percentile_window(values, CLAHE_WINDOW.0, CLAHE_WINDOW.1);
+            MappedIntensity {
+                data: clahe(values, width, height, window, tiles, clip_limit),
+                data_range,
+                window,
+            }
+        }
+    }
+}
+
+fn finite_range(values: &[f32]) -> (f32, f32) {
+    let (min, max) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &val| {
+            (min.min(val), max.max(val))
+        });
+    if min > max { (0.0, 0.0) } else { (min, max) }
+}
+
+/// Find the values at the `low` and `high` percentiles of the finite input values.
+fn percentile_window(values: &[f32], low: f32, high: f32) -> (f32, f32) {
+    let mut finite: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if finite.is_empty() {
+        return (0.0, 0.0);
+    }
+    let (low, high) = (low.clamp(0.0, 100.0), high.clamp(0.0, 100.0));
+    let (low, high) = if low <= high {
+        (low, high)
+    } else {
+        (high, low)
+    };
+    let last = finite.len() - 1;
+    let index_of = |p: f32| ((p / 100.0) * last as f32).round() as usize;
+    let (low_index, high_index) = (index_of(low), index_of(high));
+    let (_, &mut low_value, _) = finite.select_nth_unstable_by(low_index, f32::total_cmp);
+    let (_, &mut high_value, _) = finite.select_nth_unstable_by(high_index, f32::total_cmp);
+    (low_value, high_value)
+}
+
+/// Map `window.0..=window.1` linearly to 0..=255, clamping values outside of it.
+fn linear(values: &[f32], window: (f32, f32)) -> Vec<u8> {
+    let (min_val, max_val) = window;
+    let range = max_val - min_val;
+    if range <= 0.0 {
+        return vec![128u8; values.len()];
+    }
+    values
+        .iter()
+        .map(|&val| {
+            if val.is_finite() {
+                ((val - min_val) / range * 255.0).clamp(0.0, 255.0) as u8
+            } else {
+                0
+            }
+        })
+        .collect()
+}
+
+/// Quantize finite values within `window` into `levels` bins; non-finite values get `None`.
+fn quantize(val: f32, window: (f32, f32), levels: usize) -> Option<usize> {
+    if !val.is_finite() {
+        return None;
+    }
+    let range = window.1 - window.0;
+    if range <= 0.0 {
+        return Some(0);
+    }
+    let scaled = (val - window.0) / range * (levels - 1) as f32;
+    Some(scaled.clamp(0.0, (levels - 1) as f32) as usize)
+}
+
+/// Equalize by rank rather than by histogram bins, so that outliers stretching
+/// the data range don't merge everything else into a few bins.
+fn equalize(values: &[f32]) -> Vec<u8> {
+    let mut sorted: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    sorted.sort_unstable_by(f32::total_cmp);
+    let Some(&min_val) = sorted.first() else {
+        return vec![0u8; values.len()];
+    };
+    let first = sorted.partition_point(|&x| x <= min_val);
+    let denominator = sorted.len() - first;
+    values
+        .iter()
+        .map(|&val| {
+            if !val.is_finite() {
+                return 0;
+            }
+            let cumulative = sorted.partition_point(|&x| x <= val);
+            ((cumulative - first) * 255)
+                .checked_div(denominator)
+                .map_or(128, |v| v as u8)
+        })
+        .collect()
+}
+
+/// Turn a histogram into a lookup table mapping bins to equalized 8-bit values.
+fn cdf_lut(histogram: &[u32]) -> Vec<u8> {
+    let total: u64 = histogram.iter().map(|&c| c as u64).sum();
+    let first = histogram.iter().find(|&&c| c > 0).copied().unwrap_or(0) as u64;
+    let denominator = total.saturating_sub(first);
+    let mut cumulative = 0u64;
+    histogram
+        .iter()
+        .map(|&count| {
+            cumulative += count as u64;
+            (cumulative.saturating_sub(first) * 255)
+                .checked_div(denominator)
+                .map_or(128, |v| v as u8)
+        })
+        .collect()
+}
+
+fn clahe(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    window: (f32, f32),
+    tiles: u32,
+    clip_limit: f32,
+) -> Vec<u8> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let tiles_x = (tiles.max(1) as usize).min(width);
+    let tiles_y = (tiles.max(1) as usize).min(height);
+    let tile_w = width.div_ceil(tiles_x);
+    let tile_h = height.div_ceil(tiles_y);
+
+    let levels: Vec<Option<usize>> = values
+        .iter()
+        .map(|&val| quantize(val, window, CLAHE_LEVELS))
+        .collect();
+
+    // One equalization lookup table per tile, built from its clipped histogram.
+    let mut luts = Vec::with_capacity(tiles_x * tiles_y);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let mut histogram = vec![0u32; CLAHE_LEVELS];
+            let mut pixels = 0u32;
+            for y in (ty * tile_h)..((ty + 1) * tile_h).min(height) {
+                for x in (tx * tile_w)..((tx + 1) * tile_w).min(width) {
+                    if let Some(level) = levels[y * width + x] {
+                        histogram[level] += 1;
+                        pixels += 1;
+                    }
+                }
+            }
+            clip_histogram(&mut histogram, pixels, clip_limit);
+            luts.push(cdf_lut(&histogram));
+        }
+    }
+
+    // Bilinearly interpolate between the lookup tables of the four nearest tile centers.
+    let tile_coordinate = |pos: usize, tile_size: usize, count: usize| {
+        let t = (pos as f32 + 0.5) / tile_size as f32 - 0.5;
+        let t = t.clamp(0.0, (count - 1) as f32);
+        let t0 = t.floor() as usize;
+        let t1 = (t0 + 1).min(count - 1);
+        (t0, t1, t - t0 as f32)
+    };
+    let mut output = vec![0u8; width * height];
+    for y in 0..height {
+        let (y0, y1, fy) = tile_coordinate(y, tile_h, tiles_y);
+        for x in 0..width {
+            let Some(level) = levels[y * width + x] else {
+                continue;
+            };
+            let (x0, x1, fx) = tile_coordinate(x, tile_w, tiles_x);
+            let lookup = |tx: usize, ty: usize| luts[ty * tiles_x + tx][level] as f32;
+            let top = lookup(x0, y0) * (1.0 - fx) + lookup(x1, y0) * fx;
+            let bottom = lookup(x0, y1) * (1.0 - fx) + lookup(x1, y1) * fx;
+            output[y * width + x] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+        }
+    }
+    output
+}
+
+/// Clip histogram bins at `clip_limit` times the mean bin count and redistribute
+/// the excess evenly over all bins.
+fn clip_histogram(histogram: &mut [u32], pixels: u32, clip_limit: f32) {
+    if clip_limit <= 0.0 {
+        return;
+    }
+    let limit = ((clip_limit * pixels as f32 / histogram.len() as f32) as u32).max(1);
+    let mut excess = 0u32;
+    for count in histogram.iter_mut() {
+        if *count > limit {
+            excess += *count - limit;
+            *count = limit;
+        }
+    }
+    let bins = histogram.len() as u32;
+    let (per_bin, remainder) = (excess / bins, (excess % bins) as usize);
+    for (i, count) in histogram.iter_mut().enumerate() {
+        *count += per_bin + u32::from(i < remainder);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::options::JpegOptions;
+use crate::raw::to_8bit;
+use crate::typ::DecodedImage;
+use anyhow::Result;
+use jpeg_encoder::{ColorType as JpegColorType, Encoder};
+use png::ColorType;
+use std::borrow::Cow;
+
+/// Encode `image` as baseline JPEG. 16-bit data is reduced to 8 bits and any
+/// alpha channel is dropped, as JPEG supports neither.
+pub fn encode_jpeg(image: &DecodedImage, options: &JpegOptions) -> Result<Vec<u8>> {
+    let (Ok(width), Ok(height)) = (u16::try_from(image.width), u16::try_from(image.height)) else {
+        anyhow::bail!(
+            "Image size {}x{} is too large for JPEG",
+            image.width,
+            image.height
+        );
+    };
+    let data = to_8bit(image)?;
+    let (data, color) = match image.color_type {
+        ColorType::Grayscale => (data, JpegColorType::Luma),
+        ColorType::GrayscaleAlpha => (
+            Cow::Owned(data.iter().step_by(2).copied().collect()),
+            JpegColorType::Luma,
+        ),
+        ColorType::Rgb => (data, JpegColorType::Rgb),
+        ColorType::Rgba => (data, JpegColorType::Rgba),
+        ColorType::Indexed => anyhow::bail!("Indexed images can't be encoded as JPEG"),
+    };
+    let mut jpeg_data = Vec::new();
+    Encoder::new(&mut jpeg_data, options.quality)
+        .encode(&data, width, height, color)
+        .map_err(|e| anyhow::anyhow!("Failed to encode JPEG: {e}"))?;
+    Ok(jpeg_data)
+}
@@ -1,14 +1,35 @@
+mod colormap;
+pub mod compression;
+mod contact_sheet;
+pub mod format;
+pub mod image_file;
+pub mod intensity;
+mod jpeg;
 mod metadata;
 pub mod mrc;
+mod mrc_writer;
+pub mod options;
+pub mod pixel_size;
 mod png;
+pub mod raw;
+mod resize;
 pub mod tiff;
+mod tiff_writer;
 pub mod typ;
 mod utils;
+mod webp;
 
 use anyhow::Result;
-use png::encode_png;
-use typ::{DecodeResult, Image, Output};
+use format::FormatDecoder;
+use jpeg::encode_jpeg;
+use metadata::MetadataMap;
+use mrc_writer::encode_mrc;
+use options::{DecodeOptions, MetadataLevel, OutputFormat};
+use png::{encode_apng, encode_png};
+use tiff_writer::encode_tiff;
+use typ::{DecodeResult, DecodedImage, Image, Output, ProbeResult, SampleType, Samples};
 use wasm_bindgen::prelude::*;
+use webp::encode_webp;
 
 // TODO: it would be nicer to generate these automatically, with e.g.
 //       `tsify`, but I couldn't get it to work with `serde_bytes`
@@ -19,14 +40,45 @@ export interface ImageInfo {
   image_index: number;
   width: number;
   height: number;
+  /** The color type (TIFF) or mode (MRC) of the source image */
   color_type: string;
+  /** Bits per sample of the decoded image: 16 for 16-bit data kept with `preserve_depth`, 8 otherwise */
   bit_depth: number;
   metadata: Map<string, string | number | boolean> | null;
 }
 
 export interface Image {
+  /** PNG data with the "png" (default) and "apng" output formats; empty otherwise */
   png_data: Uint8Array;
+  /**
+   * The encoded image with the other output formats, or RGBA pixels (a Uint8ClampedArray)
+   * with the "rgba" output format; empty for PNG output
+   */
+  data: Uint8Array | Uint8ClampedArray;
+  format: "png" | "webp" | "jpeg" | "tiff" | "mrc" | "rgba" | "apng" | "none";
   info: ImageInfo;
+  /**
+   * The original pixel values, if requested with the `samples` option.
+   * Not available for the "apng" and "mrc" outputs, or multipage TIFFs.
+   */
+  samples?: Samples;
+}
+
+export interface Samples {
+  data:
+    | Uint8Array
+    | Int8Array
+    | Uint16Array
+    | Int16Array
+    | Uint32Array
+    | Int32Array
+    | BigUint64Array
+    | BigInt64Array
+    | Float32Array
+    | Float64Array;
+  dtype: "uint8" | "int8" | "uint16" | "int16" | "uint32" | "int32" | "uint64" | "int64" | "float32" | "float64";
+  /** [height, width, channels] */
+  shape: [number, number, number];
 }
 
 export interface ImageDecodeError {
@@ -40,31 +92,241 @@ export interface Output {
   total_images: number;
   metadata: Map<string, string | number | boolean> | null;
 }
+
+export type IntensityMapping =
+  | { mode: "min_max" }
+  | { mode: "window"; min: number; max: number }
+  | { mode: "percentile"; low?: number; high?: number }
+  | { mode: "equalize" }
+  | { mode: "clahe"; tiles?: number; clip_limit?: number };
+
+export type StackMode =
+  | { mode: "slices" }
+  | { mode: "montage"; columns?: number }
+  | { mode: "average" };
+
+export interface DecodeOptions {
+  intensity?: IntensityMapping;
+  complex?: "magnitude" | "log_magnitude" | "phase" | "phase_hue" | "real" | "imaginary";
+  frames?: { start?: number; end?: number; step?: number };
+  stack?: StackMode;
+  max_decompressed_size?: number;
+  max_width?: number;
+  max_height?: number;
+  output?: "png" | "webp" | "jpeg" | "tiff" | "mrc" | "rgba" | "apng" | "none";
+  png?: {
+    compression?: "fast" | "default" | "best";
+    filter?: "none" | "sub" | "up" | "avg" | "paeth" | "adaptive";
+    embed_metadata?: boolean;
+  };
+  apng?: { delay_ms?: number; plays?: number };
+  jpeg?: { quality?: number };
+  tiff?: { compression?: "none" | "lzw" | "deflate"; multipage?: boolean };
+  colormap?: "viridis" | "magma" | "inferno" | "cividis" | "hot" | "fire" | "rdbu" | [number, number, number][];
+  thumbnail?: { max_width?: number; max_height?: number; filter?: "area" | "lanczos" };
+  contact_sheet?: {
+    columns?: number;
+    tile_size?: number;
+    spacing?: number;
+    labels?: boolean;
+    filter?: "area" | "lanczos";
+  };
+  preserve_depth?: boolean;
+  samples?: boolean;
+  metadata?: "none" | "basic" | "full";
+}
+
+export interface ProbeOutput {
+  images: ImageInfo[];
+  errors: ImageDecodeError[];
+  total_images: number;
+  metadata: Map<string, string | number | boolean> | null;
+}
+
+export interface FormatInfo {
+  name: string;
+  extensions: string[];
+  mime_types: string[];
+}
 "#;
 
+#[wasm_bindgen(js_name = "decode", unchecked_return_type = "Output")]
+pub fn js_decode(
+    data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    js_decode_with(data, None, options)
+}
+
+#[wasm_bindgen(js_name = "decodeAs", unchecked_return_type = "Output")]
+pub fn js_decode_as(
+    format: &str,
+    data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    let format = format::find_format(format)
+        .ok_or_else(|| JsValue::from_str(&format!("Unsupported format: {format}")))?;
+    js_decode_with(data, Some(format), options)
+}
+
 #[wasm_bindgen(js_name = "decodeTiff", unchecked_return_type = "Output")]
 pub fn js_decode_tiff(
     #[wasm_bindgen(js_name = "tiffData")] tiff_data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    js_decode_with(tiff_data, Some(&tiff::TiffFormat), options)
+}
+
+#[wasm_bindgen(js_name = "decodeMrc", unchecked_return_type = "Output")]
+pub fn js_decode_mrc(
+    #[wasm_bindgen(js_name = "mrcData")] mrc_data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    js_decode_with(mrc_data, Some(&mrc::MrcFormat), options)
+}
+
+#[wasm_bindgen(js_name = "probe", unchecked_return_type = "ProbeOutput")]
+pub fn js_probe(
+    data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    js_probe_with(data, None, options)
+}
+
+#[wasm_bindgen(js_name = "probeAs", unchecked_return_type = "ProbeOutput")]
+pub fn js_probe_as(
+    format: &str,
+    data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    let format = format::find_format(format)
+        .ok_or_else(|| JsValue::from_str(&format!("Unsupported format: {format}")))?;
+    js_probe_with(data, Some(format), options)
+}
+
+#[wasm_bindgen(js_name = "probeTiff", unchecked_return_type = "ProbeOutput")]
+pub fn js_probe_tiff(
+    #[wasm_bindgen(js_name = "tiffData")] tiff_data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    js_probe_with(tiff_data, Some(&tiff::TiffFormat), options)
+}
+
+#[wasm_bindgen(js_name = "probeMrc", unchecked_return_type = "ProbeOutput")]
+pub fn js_probe_mrc(
+    #[wasm_bindgen(js_name = "mrcData")] mrc_data: &[u8],
+    #[wasm_bindgen(unchecked_param_type = "DecodeOptions")] options: Option<js_sys::Object>,
+) -> std::result::Result<JsValue, JsValue> {
+    js_probe_with(mrc_data, Some(&mrc::MrcFormat), options)
+}
+
+#[wasm_bindgen(js_name = "supportedFormats", unchecked_return_type = "FormatInfo[]")]
+pub fn js_supported_formats() -> std::result::Result<JsValue, JsValue> {
+    serde_wasm_bindgen::to_value(&format::supported_formats())
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize formats: {e}")))
+}
+
+/// Deserialize the options object passed from JavaScript, if any.
+pub(crate) fn js_options(options: Option<js_sys::Object>) -> Result<DecodeOptions> {
+    match options {
+        Some(options) => serde_wasm_bindgen::from_value::<DecodeOptions>(options.into())
+            .map_err(|e| anyhow::anyhow!("Invalid decode options: {e}"))
+            .and_then(|options| {
+                options.validate()?;
+                Ok(options)
+            }),
+        None => Ok(DecodeOptions::default()),
+    }
+}
+
+/// Decode `data` as `format`, or as whatever format is detected if `None`.
+fn js_decode_with(
+    data: &[u8],
+    format: Option<&dyn FormatDecoder>,
+    options: Option<js_sys::Object>,
 ) -> std::result::Result<JsValue, JsValue> {
     utils::set_panic_hook();
 
-    tiff::decode_tiff(tiff_data)
-        .and_then(encode_result)
-        .and_then(|result| {
-            serde_wasm_bindgen::to_value(&result)
-                .map_err(|e| anyhow::anyhow!("Failed to serialize result: {e}"))
+    js_options(options)
+        .and_then(|options| {
+            decode_with_options(data, format, &options)
+                .and_then(|result| encode_result_with_options(result, &options))
         })
+        .and_then(|result| output_to_js(&result))
         .map_err(|e| JsValue::from_str(&format!("{e}")))
 }
 
-#[wasm_bindgen(js_name = "decodeMrc", unchecked_return_type = "Output")]
-pub fn js_decode_mrc(
-    #[wasm_bindgen(js_name = "mrcData")] mrc_data: &[u8],
+/// Serialize `output` for JavaScript, see [`image_to_js`].
+fn output_to_js(output: &Output) -> Result<JsValue> {
+    let value = serde_wasm_bindgen::to_value(output)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize result: {e}"))?;
+    let images: js_sys::Array = js_sys::Reflect::get(&value, &"images".into())
+        .map_err(|_| anyhow::anyhow!("Failed to serialize result images"))?
+        .unchecked_into();
+    for (js_image, image) in images.iter().zip(&output.images) {
+        set_typed_data(&js_image, image)?;
+    }
+    Ok(value)
+}
+
+/// Serialize `image` for JavaScript. Raw pixels are passed as a `Uint8ClampedArray`
+/// (which serde can't produce), so they can go straight into `new ImageData(...)`.
+pub(crate) fn image_to_js(image: &Image) -> Result<JsValue> {
+    let value = serde_wasm_bindgen::to_value(image)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize image: {e}"))?;
+    set_typed_data(&value, image)?;
+    Ok(value)
+}
+
+fn set_typed_data(js_image: &JsValue, image: &Image) -> Result<()> {
+    let set = |target: &JsValue, key: &str, value: &JsValue| {
+        js_sys::Reflect::set(target, &key.into(), value)
+            .map(|_| ())
+            .map_err(|_| anyhow::anyhow!("Failed to set image {key}"))
+    };
+    if image.format == OutputFormat::Rgba {
+        set(
+            js_image,
+            "data",
+            &js_sys::Uint8ClampedArray::from(image.data.as_slice()).into(),
+        )?;
+    }
+    if let Some(samples) = &image.samples {
+        let js_samples = js_sys::Reflect::get(js_image, &"samples".into())
+            .map_err(|_| anyhow::anyhow!("Failed to get image samples"))?;
+        set(&js_samples, "data", &samples_to_typed_array(samples))?;
+    }
+    Ok(())
+}
+
+/// View the bytes of `samples` as the typed array matching their type.
+fn samples_to_typed_array(samples: &Samples) -> JsValue {
+    // A fresh copy starts at offset 0 of its own buffer, so it is aligned for any type.
+    let buffer = js_sys::Uint8Array::from(samples.data.as_slice()).buffer();
+    match samples.dtype {
+        SampleType::Uint8 => js_sys::Uint8Array::new(&buffer).into(),
+        SampleType::Int8 => js_sys::Int8Array::new(&buffer).into(),
+        SampleType::Uint16 => js_sys::Uint16Array::new(&buffer).into(),
+        SampleType::Int16 => js_sys::Int16Array::new(&buffer).into(),
+        SampleType::Uint32 => js_sys::Uint32Array::new(&buffer).into(),
+        SampleType::Int32 => js_sys::Int32Array::new(&buffer).into(),
+        SampleType::Uint64 => js_sys::BigUint64Array::new(&buffer).into(),
+        SampleType::Int64 => js_sys::BigInt64Array::new(&buffer).into(),
+        SampleType::Float32 => js_sys::Float32Array::new(&buffer).into(),
+        SampleType::Float64 => js_sys::Float64Array::new(&buffer).into(),
+    }
+}
+
+/// Read image information from `data` as `format`, or as whatever format is detected if `None`.
+fn js_probe_with(
+    data: &[u8],
+    format: Option<&dyn FormatDecoder>,
+    options: Option<js_sys::Object>,
 ) -> std::result::Result<JsValue, JsValue> {
     utils::set_panic_hook();
 
-    mrc::decode_mrc(mrc_data)
-        .and_then(encode_result)
+    js_options(options)
+        .and_then(|options| probe_with_options(data, format, &options))
         .and_then(|result| {
             serde_wasm_bindgen::to_value(&result)
                 .map_err(|e| anyhow::anyhow!("Failed to serialize result: {e}"))
@@ -72,31 +334,194 @@ pub fn js_decode_mrc(
         .map_err(|e| JsValue::from_str(&format!("{e}")))
 }
 
+/// Decode `data` in any supported format, decompressing it first if needed.
+/// The detected format is recorded as `format` in the file metadata.
+pub fn decode_any(data: &[u8], options: &DecodeOptions) -> Result<DecodeResult> {
+    decode_with_options(data, None, options)
+}
+
+/// Decode `data` as `format` (or the detected format if `None`), decompressing it first if needed.
+pub fn decode_with_options(
+    data: &[u8],
+    format: Option<&dyn FormatDecoder>,
+    options: &DecodeOptions,
+) -> Result<DecodeResult> {
+    let mut result = compression::decode_wrapped(data, options, |data, options| match format {
+        Some(format) => format::decode_as(format, data, options),
+        None => decode_detected(data, options),
+    })?;
+    if options.metadata == MetadataLevel::None {
+        result.strip_metadata();
+    }
+    Ok(result)
+}
+
+/// Read the image dimensions, types and metadata of `data` as `format` (or the detected
+/// format if `None`) without decoding any pixel data.
+pub fn probe_with_options(
+    data: &[u8],
+    format: Option<&dyn FormatDecoder>,
+    options: &DecodeOptions,
+) -> Result<ProbeResult> {
+    let mut result = compression::read_info_wrapped(data, options, |data, options| {
+        let format = match format {
+            Some(format) => format,
+            None => detect(data)?,
+        };
+        format::read_info_as(format, data, options)
+    })?;
+    if options.metadata == MetadataLevel::None {
+        result.strip_metadata();
+    }
+    Ok(result)
+}
+
+pub(crate) fn detect(data: &[u8]) -> Result<&'static dyn FormatDecoder> {
+    format::detect_format(data).ok_or_else(|| anyhow::anyhow!("Unrecognized image format"))
+}
+
+fn decode_detected(data: &[u8], options: &DecodeOptions) -> Result<DecodeResult> {
+    format::decode_as(detect(data)?, data, options)
+}
+
 pub fn encode_result(res: DecodeResult) -> Result<Output> {
-    let mut successful_results = Vec::new();
-
-    for decoded in res.images {
-        match encode_png(&decoded) {
-            Ok(png_data) => {
-                successful_results.push(Image {
-                    png_data,
-                    info: decoded.info,
-                });
-            }
-            Err(e) => {
-                // If PNG encoding fails, we could add it to errors, but for now we'll
-                // let the error bubble up since this is less likely than TIFF decode errors
-                return Err(e);
-            }
+    encode_result_with_options(res, &DecodeOptions::default())
+}
+
+pub fn encode_result_with_options(res: DecodeResult, options: &DecodeOptions) -> Result<Output> {
+    options.validate()?;
+    let res = match &options.contact_sheet {
+        Some(sheet) => {
+            // Color the tiles, as the sheet itself is RGB.
+            let res = match &options.colormap {
+                Some(colormap) => DecodeResult {
+                    images: res
+                        .images
+                        .into_iter()
+                        .map(|decoded| colormap::apply(decoded, colormap))
+                        .collect::<Result<_>>()?,
+                    ..res
+                },
+                None => res,
+            };
+            contact_sheet::apply(res, sheet, options)?
+        }
+        None => res,
+    };
+    if options.combine_images() {
+        return encode_combined(res, options);
+    }
+    // If encoding fails, we could add it to errors, but for now we'll let the
+    // error bubble up since this is less likely than decode errors
+    let images = res
+        .images
+        .into_iter()
+        .map(|decoded| encode_image(decoded, options, res.metadata.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let total_images = images.len() + res.errors.len();
+
+    Ok(Output {
+        images,
+        errors: res.errors,
+        total_images,
+        metadata: res.metadata,
+        encoding: options.encoding_metadata(),
+    })
+}
+
+/// Encode all decoded images into a single file, as the frames of an animated PNG,
+/// the pages of a TIFF or the sections of an MRC file.
+///
+/// The image info and metadata are those of the first image, plus the number of
+/// images as `frame_count`, `page_count` or `slice_count`. Unless the metadata level
+/// is `None`, the count is recorded even when the first image has no metadata of its
+/// own, by creating a metadata map for it.
+fn encode_combined(res: DecodeResult, options: &DecodeOptions) -> Result<Output> {
+    let res = DecodeResult {
+        images: res
+            .images
+            .into_iter()
+            .map(|decoded| prepare_image(decoded, options))
+            .collect::<Result<_>>()?,
+        ..res
+    };
+    let mut images = Vec::new();
+    if !res.images.is_empty() {
+        let (data, count_key) = match options.output {
+            OutputFormat::Apng => (
+                encode_apng(
+                    &res.images,
+                    &options.png,
+                    &options.apng,
+                    res.metadata.as_ref(),
+                )?,
+                "frame_count",
+            ),
+            OutputFormat::Mrc => (
+                encode_mrc(&res.images, res.metadata.as_ref())?,
+                "slice_count",
+            ),
+            _ => (encode_tiff(&res.images, &options.tiff)?, "page_count"),
+        };
+        let count = res.images.len();
+        let first = res.images.into_iter().next().unwrap();
+        let mut info = first.info;
+        if options.metadata != MetadataLevel::None {
+            let metadata = info.metadata.get_or_insert_default();
+            metadata.insert(count_key.to_string(), count.into());
         }
+        images.push(Image::new(data, options.output, info, None));
     }
 
-    let total_images = successful_results.len() + res.errors.len();
+    let total_images = images.len() + res.errors.len();
 
     Ok(Output {
-        images: successful_results,
+        images,
         errors: res.errors,
         total_images,
         metadata: res.metadata,
+        encoding: options.encoding_metadata(),
     })
 }
+
+/// Apply the image-level output options (thumbnails, colormaps) before encoding.
+fn prepare_image(decoded: DecodedImage, options: &DecodeOptions) -> Result<DecodedImage> {
+    let mut decoded = decoded;
+    if let Some(thumbnail) = &options.thumbnail {
+        decoded = resize::thumbnail(decoded, thumbnail)?;
+    }
+    // After resizing, so the data is interpolated rather than the colors.
+    if let Some(colormap) = &options.colormap {
+        decoded = colormap::apply(decoded, colormap)?;
+    }
+    Ok(decoded)
+}
+
+/// Encode a single decoded image in the output format selected by `options`.
+/// The file metadata is embedded along with the image's own if requested.
+pub fn encode_image(
+    decoded: DecodedImage,
+    options: &DecodeOptions,
+    file_metadata: Option<&MetadataMap>,
+) -> Result<Image> {
+    let decoded = prepare_image(decoded, options)?;
+    let data = match options.output {
+        OutputFormat::Png => encode_png(&decoded, &options.png, file_metadata)?,
+        OutputFormat::Rgba => raw::to_rgba8(&decoded)?,
+        OutputFormat::Webp => encode_webp(&decoded)?,
+        OutputFormat::Jpeg => encode_jpeg(&decoded, &options.jpeg)?,
+        OutputFormat::Tiff => encode_tiff(std::slice::from_ref(&decoded), &options.tiff)?,
+        OutputFormat::Mrc => encode_mrc(std::slice::from_ref(&decoded), file_metadata)?,
+        OutputFormat::Apng => encode_apng(
+            std::slice::from_ref(&decoded),
+            &options.png,
+            &options.apng,
+            file_metadata,
+        )?,
+        OutputFormat::None => Vec::new(),
+    };
+    // Samples may have been kept only to write the output from.
+    let samples = decoded.samples.filter(|_| options.samples);
+    Ok(Image::new(data, options.output, decoded.info, samples))
+}
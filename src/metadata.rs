@@ -2,7 +2,7 @@ use serde::Serialize;
 use std::collections::HashMap;
 use std::fmt::Display;
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 #[serde(untagged)]
 pub enum MetadataValue {
     String(String),
@@ -57,6 +57,10 @@ impl_from_for_metadata! {
 
 impl_from_for_metadata! {
     i32 => Integer as i64,
+    u8 => Integer as i64,
+    u16 => Integer as i64,
+    u32 => Integer as i64,
+    usize => Integer as i64,
     f32 => Number as f64,
 }
 pub type MetadataMap = HashMap<String, MetadataValue>;
@@ -1,9 +1,18 @@
+use crate::format::FormatDecoder;
+use crate::intensity::{IntensityMapping, map_to_u8};
 use crate::metadata::MetadataValue;
-use crate::typ::{DecodeResult, DecodedImage, ImageDecodeError, ImageInfo};
+use crate::options::{DecodeOptions, MetadataLevel};
+use crate::pixel_size::{LengthUnit, PixelSize, positive};
+use crate::raw::to_be_bytes;
+use crate::typ::{
+    DecodeResult, DecodedImage, ImageDecodeError, ImageInfo, ProbeResult, SampleType, Samples,
+};
 use anyhow::{Result, anyhow};
 use mrc::{Header, Mode};
-use png::ColorType;
+use png::{BitDepth, ColorType};
+use serde::Deserialize;
 use std::collections::HashMap;
+use std::f32::consts::PI;
 use std::fmt::Display;
 
 macro_rules! md_item {
@@ -16,6 +25,12 @@ macro_rules! md_item_string {
         ($key.to_string(), MetadataValue::String($val))
     };
 }
+/// Number of 80-character labels that fit in the header.
+const MAX_LABELS: usize = 10;
+
+/// NVERSION values defined by the MRC2014 format (plus 0 for older files).
+const KNOWN_NVERSIONS: [i32; 3] = [0, 20140, 20141];
+
 enum Endianness {
     Little,
     Big,
@@ -33,8 +48,9 @@ impl Display for Endianness {
 }
 
 impl Endianness {
+    /// The stamp is compared as the bytes appear in the file, e.g. `44 44 00 00` for little-endian.
     fn from_machst(machst: &[u8; 4]) -> Self {
-        match u32::from_le_bytes(*machst) {
+        match u32::from_be_bytes(*machst) {
             0x44440000 => Endianness::Little,
             0x44410000 => Endianness::Little,
             0x11110000 => Endianness::Big,
@@ -43,6 +59,96 @@ impl Endianness {
     }
 }
 
+/// How complex-valued slices (`Int16Complex`, `Float32Complex`) are visualised.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplexMapping {
+    /// Linear magnitude `|z|`.
+    #[default]
+    Magnitude,
+    /// `ln(1 + |z|)`, the usual way to look at FFT data.
+    LogMagnitude,
+    /// Phase angle in radians, as grayscale.
+    Phase,
+    /// Phase as hue on an HSV colour wheel, with log-magnitude as value.
+    PhaseHue,
+    /// Real component.
+    Real,
+    /// Imaginary component.
+    Imaginary,
+}
+
+impl ComplexMapping {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComplexMapping::Magnitude => "magnitude",
+            ComplexMapping::LogMagnitude => "log_magnitude",
+            ComplexMapping::Phase => "phase",
+            ComplexMapping::PhaseHue => "phase_hue",
+            ComplexMapping::Real => "real",
+            ComplexMapping::Imaginary => "imaginary",
+        }
+    }
+
+    /// The scalar value shown for `real + imag * i`.
+    /// `PhaseHue` yields the log-magnitude, its phase goes into the hue separately.
+    fn component(&self, real: f32, imag: f32) -> f32 {
+        match self {
+            ComplexMapping::Magnitude => real.hypot(imag),
+            ComplexMapping::LogMagnitude | ComplexMapping::PhaseHue => real.hypot(imag).ln_1p(),
+            ComplexMapping::Phase => imag.atan2(real),
+            ComplexMapping::Real => real,
+            ComplexMapping::Imaginary => imag,
+        }
+    }
+}
+
+fn is_complex(mode: Mode) -> bool {
+    matches!(mode, Mode::Int16Complex | Mode::Float32Complex)
+}
+
+/// The intensity mapping used for `mode` if none is configured. Phase is shown over
+/// its full range, so the same gray level means the same phase in every image.
+fn default_mapping(mode: Mode, complex: ComplexMapping) -> IntensityMapping {
+    match complex {
+        ComplexMapping::Phase if is_complex(mode) => IntensityMapping::Window { min: -PI, max: PI },
+        _ => IntensityMapping::MinMax,
+    }
+}
+
+/// Whether slices are read as `[real, imaginary]` pairs rather than one value per pixel.
+fn reads_pairs(mode: Mode, complex: ComplexMapping) -> bool {
+    is_complex(mode) && complex == ComplexMapping::PhaseHue
+}
+
+/// Read a slice for a composite: interleaved `[real, imaginary]` pairs for
+/// [`ComplexMapping::PhaseHue`], one value per pixel otherwise.
+fn composite_values(mode: Mode, slice_data: &[u8], complex: ComplexMapping) -> Result<Vec<f32>> {
+    if reads_pairs(mode, complex) {
+        Ok(complex_pairs(mode, slice_data)?.into_flattened())
+    } else {
+        slice_to_f32(mode, slice_data, complex)
+    }
+}
+
+/// How the selected slices of a stack or volume are turned into images.
+///
+/// Deserialized from e.g. `{ "mode": "montage", "columns": 10 }`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StackMode {
+    /// One image per slice.
+    #[default]
+    Slices,
+    /// A single grid image of all slices, `columns` wide (roughly square if `None`).
+    Montage {
+        #[serde(default)]
+        columns: Option<u32>,
+    },
+    /// A single image of the mean of all slices, e.g. for dose-fractionated movies.
+    Average,
+}
+
 fn mrc_header_to_metadata(header: &Header) -> HashMap<String, MetadataValue> {
     let Header {
         nx,
@@ -117,7 +223,7 @@ fn mrc_header_to_metadata(header: &Header) -> HashMap<String, MetadataValue> {
         md_item!("origin_y", &origin[1]),
         md_item!("origin_z", &origin[2]),
     ]);
-    for n in 0..(*nlabl as usize) {
+    for n in 0..(*nlabl).clamp(0, MAX_LABELS as i32) as usize {
         let slice = &label[n * 80..(n + 1) * 80];
         let label = String::from_utf8_lossy(slice).to_string();
         if !label.trim().is_empty() {
@@ -128,6 +234,39 @@ fn mrc_header_to_metadata(header: &Header) -> HashMap<String, MetadataValue> {
 }
 
 pub fn decode_mrc(data: &[u8]) -> Result<DecodeResult> {
+    decode_mrc_with_options(data, &DecodeOptions::default())
+}
+
+pub struct MrcFormat;
+
+impl FormatDecoder for MrcFormat {
+    fn name(&self) -> &'static str {
+        "mrc"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        // Volumes, particle stacks, maps, tomographic reconstructions and IMOD tilt series
+        &["mrc", "mrcs", "map", "rec", "st", "ali"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/x-mrc"]
+    }
+
+    fn probe(&self, data: &[u8]) -> bool {
+        is_mrc(data)
+    }
+
+    fn decode(&self, data: &[u8], options: &DecodeOptions) -> Result<DecodeResult> {
+        decode_mrc_with_options(data, options)
+    }
+
+    fn read_info(&self, data: &[u8], options: &DecodeOptions) -> Result<ProbeResult> {
+        read_mrc_info(data, options)
+    }
+}
+
+fn read_header(data: &[u8]) -> Result<Header> {
     // Parse the header from the byte data
     if data.len() < 1024 {
         anyhow::bail!("MRC file too small to contain valid header");
@@ -137,123 +276,582 @@ pub fn decode_mrc(data: &[u8]) -> Result<DecodeResult> {
     // * Header is #[repr(C)] and has no padding
     // * We checked data.len() >= 1024 above
     // * Header fields are all plain data (integers and floats, no pointers)
-    let header = unsafe { std::ptr::read_unaligned(data.as_ptr() as *const Header) };
+    Ok(unsafe { std::ptr::read_unaligned(data.as_ptr() as *const Header) })
+}
+
+/// Whether `data` looks like an MRC file: either it carries the MRC2014 `MAP ` signature,
+/// or (for older files) its header is consistent with the file and has a sane axis mapping.
+pub fn is_mrc(data: &[u8]) -> bool {
+    let Ok(header) = read_header(data) else {
+        return false;
+    };
+    &header.map == b"MAP "
+        || (validate_header(&header, data.len()).is_ok() && has_valid_axes(&header))
+}
+
+pub fn decode_mrc_with_options(data: &[u8], options: &DecodeOptions) -> Result<DecodeResult> {
+    let header = read_header(data)?;
+    let warnings = validate_header(&header, data.len())?;
 
     let mut images = Vec::new();
     let mut errors = Vec::new();
 
     // MRC files can contain multiple 2D slices in a 3D volume
     let nz = header.nz as usize;
+    let frames: Vec<usize> = options.frames.indices(nz).collect();
 
-    for z in 0..nz {
-        match decode_slice(data, &header, z) {
+    match options.stack {
+        StackMode::Slices => {
+            for z in frames {
+                match decode_slice(data, &header, z, options) {
+                    Ok(image) => images.push(image),
+                    Err(e) => errors.push(ImageDecodeError {
+                        image_index: z,
+                        message: format!("Failed to decode slice {z}: {e}"),
+                    }),
+                }
+            }
+        }
+        StackMode::Average => match average_frames(data, &header, &frames, options) {
             Ok(image) => images.push(image),
             Err(e) => errors.push(ImageDecodeError {
-                image_index: z,
-                message: format!("Failed to decode slice {z}: {e}"),
+                image_index: 0,
+                message: format!("Failed to average frames: {e}"),
             }),
+        },
+        StackMode::Montage { columns } => {
+            match montage_frames(data, &header, &frames, columns, options) {
+                Ok(image) => images.push(image),
+                Err(e) => errors.push(ImageDecodeError {
+                    image_index: 0,
+                    message: format!("Failed to build montage: {e}"),
+                }),
+            }
+        }
+    }
+
+    if let Some(pixel_size) = mrc_pixel_size(&header) {
+        for image in &mut images {
+            pixel_size.add_metadata(image.info.metadata.get_or_insert_with(HashMap::new));
         }
     }
 
     Ok(DecodeResult {
         images,
         errors,
-        metadata: Some(mrc_header_to_metadata(&header)),
+        metadata: Some(file_metadata(&header, warnings, options)),
     })
 }
 
-fn decode_slice(data: &[u8], header: &Header, slice_index: usize) -> Result<DecodedImage> {
-    let width = header.nx as u32;
-    let height = header.ny as u32;
-    let pixels_per_slice = (width * height) as usize;
+/// Describe the images [`decode_mrc_with_options`] would return, reading only the header.
+pub fn read_mrc_info(data: &[u8], options: &DecodeOptions) -> Result<ProbeResult> {
+    let header = read_header(data)?;
+    let warnings = validate_header(&header, data.len())?;
+    let mode = header_mode(&header)?;
+    let frames: Vec<usize> = options.frames.indices(header.nz as usize).collect();
 
-    let mode =
-        Mode::from_i32(header.mode).ok_or_else(|| anyhow!("Unknown MRC mode: {}", header.mode))?;
-    let bytes_per_pixel = mode.byte_size();
-    let slice_size = pixels_per_slice * bytes_per_pixel;
-    let offset = header.data_offset() + slice_index * slice_size;
+    let info = |image_index, width, height, bit_depth, metadata| ImageInfo {
+        image_index,
+        width,
+        height,
+        color_type: format!("{mode:?}"),
+        bit_depth,
+        metadata: Some(metadata),
+    };
+    // The same checks and error messages as when decoding.
+    let (width, height) = (header.nx as u32, header.ny as u32);
+    let mut images = Vec::new();
+    let mut errors = Vec::new();
+    match options.stack {
+        StackMode::Slices => {
+            for z in frames {
+                let checked = options
+                    .check_dimensions(width, height)
+                    .and_then(|()| slice_bytes(data, &header, mode, z));
+                match checked {
+                    Ok(_) => {
+                        let bit_depth = slice_depth(mode, options);
+                        images.push(info(z, width, height, bit_depth, HashMap::new()));
+                    }
+                    Err(e) => errors.push(ImageDecodeError {
+                        image_index: z,
+                        message: format!("Failed to decode slice {z}: {e}"),
+                    }),
+                }
+            }
+        }
+        StackMode::Average => {
+            match check_frames(&frames).and_then(|()| options.check_dimensions(width, height)) {
+                Ok(()) => images.push(info(0, width, height, 8, average_metadata(&frames))),
+                Err(e) => errors.push(ImageDecodeError {
+                    image_index: 0,
+                    message: format!("Failed to average frames: {e}"),
+                }),
+            }
+        }
+        StackMode::Montage { columns } => {
+            match montage_layout(&header, &frames, columns, options) {
+                Ok((columns, rows)) => {
+                    let metadata = montage_metadata(&header, &frames, columns, rows);
+                    let (columns, rows) = (columns as u32, rows as u32);
+                    images.push(info(0, width * columns, height * rows, 8, metadata));
+                }
+                Err(e) => errors.push(ImageDecodeError {
+                    image_index: 0,
+                    message: format!("Failed to build montage: {e}"),
+                }),
+            }
+        }
+    }
 
-    if offset + slice_size > data.len() {
-        anyhow::bail!("Slice {} extends beyond file boundaries", slice_index);
+    if let Some(pixel_size) = mrc_pixel_size(&header) {
+        for image in &mut images {
+            pixel_size.add_metadata(image.metadata.get_or_insert_with(HashMap::new));
+        }
     }
 
-    let slice_data = &data[offset..offset + slice_size];
+    Ok(ProbeResult::new(
+        images,
+        errors,
+        Some(file_metadata(&header, warnings, options)),
+    ))
+}
 
-    let (png_color_type, converted_data, range) = match mode {
-        Mode::Int8 => {
-            let converted_data: Vec<u8> = slice_data
-                .iter()
-                .map(|&val| (val as i8 as i16 + 128) as u8)
-                .collect();
-            (
-                ColorType::Grayscale,
-                converted_data,
-                (i8::MIN as f32, i8::MAX as f32),
-            )
+/// The raw header fields (at the full metadata level) and any validation warnings.
+fn file_metadata(
+    header: &Header,
+    warnings: Vec<String>,
+    options: &DecodeOptions,
+) -> HashMap<String, MetadataValue> {
+    // Warnings are derived information, so they are kept at the basic level.
+    let mut metadata = match options.metadata {
+        MetadataLevel::Full => mrc_header_to_metadata(header),
+        _ => HashMap::new(),
+    };
+    for (n, warning) in warnings.into_iter().enumerate() {
+        metadata.insert(format!("warning_{n}"), MetadataValue::String(warning));
+    }
+    metadata
+}
+
+/// The bit depth slices of `mode` are decoded to, as in [`decode_slice`].
+/// Averages and montages are always 8-bit.
+fn slice_depth(mode: Mode, options: &DecodeOptions) -> u8 {
+    match mode {
+        Mode::Int16 | Mode::Uint16 if options.preserve_depth && options.intensity.is_none() => 16,
+        _ => 8,
+    }
+}
+
+/// Voxel size in Ångström, from the cell dimensions and the sampling along each axis.
+fn mrc_pixel_size(header: &Header) -> Option<PixelSize> {
+    let size = |length: f32, samples: i32| positive(length as f64 / samples as f64);
+    Some(PixelSize {
+        x: size(header.xlen, header.mx)?,
+        y: size(header.ylen, header.my)?,
+        z: size(header.zlen, header.mz),
+        unit: LengthUnit::Angstrom,
+        source: "mrc_header",
+    })
+}
+
+/// Size in bytes of a single slice, if it doesn't overflow.
+fn slice_size(header: &Header, mode: Mode) -> Option<usize> {
+    usize::try_from(header.nx)
+        .ok()?
+        .checked_mul(usize::try_from(header.ny).ok()?)?
+        .checked_mul(mode.byte_size())
+}
+
+/// Check the header for consistency with itself and with the file length.
+///
+/// Files that can't possibly be decoded are rejected with an error;
+/// recoverable oddities are returned as human-readable warnings.
+fn validate_header(header: &Header, file_len: usize) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+
+    for (name, dim) in [("nx", header.nx), ("ny", header.ny), ("nz", header.nz)] {
+        if dim <= 0 {
+            anyhow::bail!("Invalid MRC header: {name} must be positive, got {dim}");
         }
-        Mode::Int16 => {
-            let int16_data: &[i16] = bytemuck::cast_slice(slice_data);
-            let converted_data: Vec<u8> = int16_data
-                .iter()
-                .map(|&val| ((val as i32 + 32768) / 256) as u8)
-                .collect();
-            (
-                ColorType::Grayscale,
-                converted_data,
-                (i16::MIN as f32, i16::MAX as f32),
+    }
+    let mode = header_mode(header)?;
+    if header.nsymbt < 0 {
+        anyhow::bail!(
+            "Invalid MRC header: negative extended header size {}",
+            header.nsymbt
+        );
+    }
+    let data_offset = header.data_offset();
+    if data_offset > file_len {
+        anyhow::bail!(
+            "Invalid MRC header: extended header of {} bytes extends beyond the end of the file",
+            header.nsymbt
+        );
+    }
+    let slice_size = slice_size(header, mode)
+        .filter(|&size| size <= file_len - data_offset)
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid MRC header: a {}x{} {mode:?} slice does not fit in the file",
+                header.nx,
+                header.ny
             )
+        })?;
+
+    let available_slices = (file_len - data_offset) / slice_size;
+    let nz = header.nz as usize;
+    if available_slices < nz {
+        warnings.push(format!(
+            "File is truncated: header declares {nz} slices, but only {available_slices} are present"
+        ));
+    } else if file_len - data_offset > nz * slice_size {
+        warnings.push(format!(
+            "File has {} unexpected trailing bytes",
+            file_len - data_offset - nz * slice_size
+        ));
+    }
+
+    if &header.map != b"MAP " {
+        warnings.push(format!(
+            "Missing MAP signature (found {:?})",
+            String::from_utf8_lossy(&header.map)
+        ));
+    }
+    if let Endianness::Unknown(code) = Endianness::from_machst(&header.machst) {
+        warnings.push(format!("Unknown machine stamp {code:#X}"));
+    }
+    let nversion = header.nversion();
+    if !KNOWN_NVERSIONS.contains(&nversion) {
+        warnings.push(format!("Unknown NVERSION {nversion}"));
+    }
+    if !(0..=MAX_LABELS as i32).contains(&header.nlabl) {
+        warnings.push(format!(
+            "Invalid label count {}, reading at most {MAX_LABELS} labels",
+            header.nlabl
+        ));
+    }
+    if !has_valid_axes(header) {
+        warnings.push(format!(
+            "Axis mapping ({}, {}, {}) is not a permutation of (1, 2, 3)",
+            header.mapc, header.mapr, header.maps
+        ));
+    }
+
+    Ok(warnings)
+}
+
+fn has_valid_axes(header: &Header) -> bool {
+    let mut axes = [header.mapc, header.mapr, header.maps];
+    axes.sort_unstable();
+    axes == [1, 2, 3]
+}
+
+fn header_mode(header: &Header) -> Result<Mode> {
+    Mode::from_i32(header.mode).ok_or_else(|| anyhow!("Unknown MRC mode: {}", header.mode))
+}
+
+/// Find the raw bytes of slice `slice_index`.
+fn slice_bytes<'a>(
+    data: &'a [u8],
+    header: &Header,
+    mode: Mode,
+    slice_index: usize,
+) -> Result<&'a [u8]> {
+    let slice_size = slice_size(header, mode).ok_or_else(|| anyhow!("Slice size overflows"))?;
+    slice_index
+        .checked_mul(slice_size)
+        .and_then(|start| start.checked_add(header.data_offset()))
+        .and_then(|offset| data.get(offset..offset.checked_add(slice_size)?))
+        .ok_or_else(|| anyhow!("Slice {} extends beyond file boundaries", slice_index))
+}
+
+/// Average the selected frames (e.g. of a dose-fractionated movie) into a single image.
+fn average_frames(
+    data: &[u8],
+    header: &Header,
+    frames: &[usize],
+    options: &DecodeOptions,
+) -> Result<DecodedImage> {
+    let mode = header_mode(header)?;
+    check_frames(frames)?;
+    let (width, height) = (header.nx as u32, header.ny as u32);
+    options.check_dimensions(width, height)?;
+    // Complex values are averaged as such, rather than their phases.
+    let channels = if reads_pairs(mode, options.complex) {
+        2
+    } else {
+        1
+    };
+    let mut sum = vec![0f32; width as usize * height as usize * channels];
+    for &z in frames {
+        let values = composite_values(mode, slice_bytes(data, header, mode, z)?, options.complex)?;
+        for (acc, val) in sum.iter_mut().zip(values) {
+            *acc += val;
         }
-        Mode::Float32 => {
-            // 32-bit float -> convert to 8-bit grayscale
-            let float_data: &[f32] = bytemuck::cast_slice(slice_data);
-            f32_to_grayscale(float_data)?
-        }
-        Mode::Int16Complex => {
-            // Complex 16-bit -> convert magnitude to 8-bit grayscale
-            let complex_data: &[[i16; 2]] = bytemuck::cast_slice(slice_data);
+    }
+    let count = frames.len() as f32;
+    sum.iter_mut().for_each(|val| *val /= count);
 
-            let magnitudes: Vec<f32> = complex_data
-                .iter()
-                .map(|&[real, imag]| ((real as f32).powi(2) + (imag as f32).powi(2)).sqrt())
-                .collect();
+    let metadata = average_metadata(frames);
+    Ok(map_composite(sum, width, height, mode, options, metadata))
+}
+
+/// Tile the selected frames (e.g. particles of a stack) into a grid image,
+/// `columns` wide or roughly square if not given. All tiles share one intensity mapping.
+fn montage_frames(
+    data: &[u8],
+    header: &Header,
+    frames: &[usize],
+    columns: Option<u32>,
+    options: &DecodeOptions,
+) -> Result<DecodedImage> {
+    let mode = header_mode(header)?;
+    let (columns, rows) = montage_layout(header, frames, columns, options)?;
+    // Make sure all frames are present before allocating the whole grid.
+    slice_bytes(data, header, mode, frames[frames.len() - 1])?;
+    let (tile_width, tile_height) = (header.nx as usize, header.ny as usize);
+    let overflow = || anyhow!("Montage size overflows");
+    let width = tile_width.checked_mul(columns).ok_or_else(overflow)?;
+    let height = tile_height.checked_mul(rows).ok_or_else(overflow)?;
 
-            f32_to_grayscale(&magnitudes)?
+    // Unused cells stay NaN, which maps to black.
+    let channels = if reads_pairs(mode, options.complex) {
+        2
+    } else {
+        1
+    };
+    let len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(overflow)?;
+    let mut grid = vec![f32::NAN; len];
+    for (i, &z) in frames.iter().enumerate() {
+        let values = composite_values(mode, slice_bytes(data, header, mode, z)?, options.complex)?;
+        let (x0, y0) = ((i % columns) * tile_width, (i / columns) * tile_height);
+        for (row, tile_row) in values.chunks_exact(tile_width * channels).enumerate() {
+            let start = ((y0 + row) * width + x0) * channels;
+            grid[start..start + tile_row.len()].copy_from_slice(tile_row);
         }
-        Mode::Uint8 => {
+    }
+
+    let metadata = montage_metadata(header, frames, columns, rows);
+    Ok(map_composite(
+        grid,
+        width as u32,
+        height as u32,
+        mode,
+        options,
+        metadata,
+    ))
+}
+
+fn check_frames(frames: &[usize]) -> Result<()> {
+    if frames.is_empty() {
+        anyhow::bail!("No frames selected");
+    }
+    Ok(())
+}
+
+/// Columns and rows of the montage of `frames`, after checking its size against the limits.
+fn montage_layout(
+    header: &Header,
+    frames: &[usize],
+    columns: Option<u32>,
+    options: &DecodeOptions,
+) -> Result<(usize, usize)> {
+    check_frames(frames)?;
+    let (columns, rows) = montage_grid(frames.len(), columns);
+    let size = |tile: i32, count: usize| {
+        (tile as usize)
+            .checked_mul(count)
+            .and_then(|size| u32::try_from(size).ok())
+            .ok_or_else(|| anyhow!("Montage size overflows"))
+    };
+    options.check_dimensions(size(header.nx, columns)?, size(header.ny, rows)?)?;
+    Ok((columns, rows))
+}
+
+/// Columns and rows of a montage of `count` tiles, `columns` wide or roughly square if not given.
+pub(crate) fn montage_grid(count: usize, columns: Option<u32>) -> (usize, usize) {
+    let columns = columns
+        .map(|c| c.max(1) as usize)
+        .unwrap_or_else(|| (count as f64).sqrt().ceil() as usize)
+        .min(count);
+    (columns, count.div_ceil(columns))
+}
+
+fn average_metadata(frames: &[usize]) -> HashMap<String, MetadataValue> {
+    let mut metadata = frames_metadata(frames);
+    metadata.extend([md_item_string!("stack_mode", "average".to_string())]);
+    metadata
+}
+
+fn montage_metadata(
+    header: &Header,
+    frames: &[usize],
+    columns: usize,
+    rows: usize,
+) -> HashMap<String, MetadataValue> {
+    let mut metadata = frames_metadata(frames);
+    metadata.extend([
+        md_item_string!("stack_mode", "montage".to_string()),
+        md_item!("montage_columns", &columns),
+        md_item!("montage_rows", &rows),
+        md_item!("tile_width", &header.nx),
+        md_item!("tile_height", &header.ny),
+    ]);
+    metadata
+}
+
+fn frames_metadata(frames: &[usize]) -> HashMap<String, MetadataValue> {
+    HashMap::from([
+        md_item!("frame_count", &frames.len()),
+        md_item!("first_frame", &frames[0]),
+        md_item!("last_frame", &frames[frames.len() - 1]),
+    ])
+}
+
+/// Map an image combined from several frames to 8-bit grayscale, or to RGB for
+/// [`ComplexMapping::PhaseHue`] (in which case `values` are `[real, imaginary]` pairs).
+fn map_composite(
+    values: Vec<f32>,
+    width: u32,
+    height: u32,
+    mode: Mode,
+    options: &DecodeOptions,
+    mut metadata: HashMap<String, MetadataValue>,
+) -> DecodedImage {
+    let mapping = options
+        .intensity
+        .unwrap_or_else(|| default_mapping(mode, options.complex));
+    if is_complex(mode) {
+        metadata.insert("complex_mapping".to_string(), options.complex.name().into());
+    }
+    let (color_type, data, range) = if reads_pairs(mode, options.complex) {
+        let pairs: Vec<[f32; 2]> = values
+            .chunks_exact(2)
+            .map(|pair| [pair[0], pair[1]])
+            .collect();
+        let (data, range) = map_phase_hue(&pairs, width, height, &mapping, &mut metadata);
+        (ColorType::Rgb, data, range)
+    } else {
+        let mapped = map_to_u8(&values, width as usize, height as usize, &mapping);
+        mapped.add_metadata(&mapping, &mut metadata);
+        (ColorType::Grayscale, mapped.data, mapped.data_range)
+    };
+    metadata.extend([
+        md_item!("min_value", &range.0),
+        md_item!("max_value", &range.1),
+    ]);
+    DecodedImage {
+        width,
+        height,
+        color_type,
+        depth: BitDepth::Eight,
+        data,
+        info: ImageInfo {
+            image_index: 0,
+            width,
+            height,
+            color_type: format!("{mode:?}"),
+            bit_depth: 8,
+            metadata: Some(metadata),
+        },
+        samples: options
+            .decode_samples()
+            .then(|| Samples::new(&values, width, height)),
+        icc_profile: None,
+    }
+}
+
+fn decode_slice(
+    data: &[u8],
+    header: &Header,
+    slice_index: usize,
+    options: &DecodeOptions,
+) -> Result<DecodedImage> {
+    let width = header.nx as u32;
+    let height = header.ny as u32;
+    options.check_dimensions(width, height)?;
+
+    let mode = header_mode(header)?;
+    let slice_data = slice_bytes(data, header, mode, slice_index)?;
+    let samples = options
+        .decode_samples()
+        .then(|| slice_samples(mode, slice_data, width, height))
+        .transpose()?;
+    let mut metadata = HashMap::new();
+    if is_complex(mode) {
+        metadata.insert("complex_mapping".to_string(), options.complex.name().into());
+    }
+
+    // 8-bit unsigned data is shown as-is by default; everything else goes through
+    // an intensity mapping (min/max of the slice unless configured otherwise).
+    let (png_color_type, converted_data, range, depth) = match (mode, options.intensity) {
+        (Mode::Uint8, None) => {
             // 8-bit unsigned integer -> direct copy
+            let range = slice_data
+                .iter()
+                .fold((u8::MAX, u8::MIN), |(min, max), &val| {
+                    (min.min(val), max.max(val))
+                });
             (
                 ColorType::Grayscale,
                 slice_data.to_vec(),
-                (u8::MIN as f32, u8::MAX as f32),
+                (range.0 as f32, range.1 as f32),
+                BitDepth::Eight,
             )
         }
-        Mode::Float32Complex => {
-            // Complex 32-bit float -> convert magnitude to 8-bit grayscale
-            let complex_data: &[[f32; 2]] = bytemuck::cast_slice(slice_data);
-
-            let magnitudes: Vec<f32> = complex_data
+        (Mode::Int16 | Mode::Uint16, None) if options.preserve_depth => {
+            // 16-bit integers -> 16-bit PNG, shifting signed values to be non-negative
+            let (values, offset): (Vec<u16>, i32) = match mode {
+                Mode::Int16 => {
+                    let int16_data: Vec<i16> = read_values(slice_data);
+                    let values = int16_data.iter().map(|&val| (val as i32 + 32768) as u16);
+                    (values.collect(), 32768)
+                }
+                _ => (read_values(slice_data), 0),
+            };
+            if offset != 0 {
+                metadata.insert("value_offset".to_string(), offset.into());
+            }
+            let range = values
                 .iter()
-                .map(|&[real, imag]| (real.powi(2) + imag.powi(2)).sqrt())
-                .collect();
-
-            f32_to_grayscale(&magnitudes)?
+                .fold((u16::MAX, u16::MIN), |(min, max), &val| {
+                    (min.min(val), max.max(val))
+                });
+            (
+                ColorType::Grayscale,
+                to_be_bytes(&values),
+                (
+                    (range.0 as i32 - offset) as f32,
+                    (range.1 as i32 - offset) as f32,
+                ),
+                BitDepth::Sixteen,
+            )
         }
-        Mode::Float16 => {
-            // 16-bit half-precision float -> convert to 8-bit grayscale
-            let float16_data: &[u16] = bytemuck::cast_slice(slice_data);
-
-            // Convert half-precision floats to f32
-            let float_values: Vec<f32> = float16_data
-                .iter()
-                .map(|&bits| half::f16::from_bits(bits).to_f32())
-                .collect();
-
-            f32_to_grayscale(&float_values)?
+        (mode, mapping) if reads_pairs(mode, options.complex) => {
+            let pairs = complex_pairs(mode, slice_data)?;
+            let mapping = mapping.unwrap_or(IntensityMapping::MinMax);
+            let (rgb_data, range) = map_phase_hue(&pairs, width, height, &mapping, &mut metadata);
+            (ColorType::Rgb, rgb_data, range, BitDepth::Eight)
+        }
+        (mode, mapping) => {
+            let values = slice_to_f32(mode, slice_data, options.complex)?;
+            let mapping = mapping.unwrap_or_else(|| default_mapping(mode, options.complex));
+            let mapped = map_to_u8(&values, width as usize, height as usize, &mapping);
+            mapped.add_metadata(&mapping, &mut metadata);
+            (
+                ColorType::Grayscale,
+                mapped.data,
+                mapped.data_range,
+                BitDepth::Eight,
+            )
         }
-        _ => anyhow::bail!("Unsupported MRC mode: {:?}", mode),
     };
 
-    let metadata = HashMap::from([
+    metadata.extend([
         md_item!("min_value", &range.0),
         md_item!("max_value", &range.1),
     ]);
@@ -262,7 +860,7 @@ fn decode_slice(data: &[u8], header: &Header, slice_index: usize) -> Result<Deco
         width,
         height,
         color_type: format!("{mode:?}"),
-        bit_depth: 8,
+        bit_depth: if depth == BitDepth::Sixteen { 16 } else { 8 },
         metadata: Some(metadata),
     };
 
@@ -270,25 +868,124 @@ fn decode_slice(data: &[u8], header: &Header, slice_index: usize) -> Result<Deco
         width,
         height,
         color_type: png_color_type,
+        depth,
         data: converted_data,
         info: metadata,
+        samples,
+        icc_profile: None,
     })
 }
 
-fn f32_to_grayscale(values: &[f32]) -> Result<(ColorType, Vec<u8>, (f32, f32))> {
-    let (min_val, max_val) = values
-        .iter()
-        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &val| {
-            (min.min(val), max.max(val))
-        });
-    let range = max_val - min_val;
-    let converted_data: Vec<u8> = if range == 0.0 {
-        vec![128u8; values.len()]
-    } else {
-        values
+/// The original values of a slice, with the real and imaginary parts of complex data
+/// as two channels. Half floats are widened to 32 bits.
+fn slice_samples(mode: Mode, slice_data: &[u8], width: u32, height: u32) -> Result<Samples> {
+    let (dtype, channels) = match mode {
+        Mode::Int8 => (SampleType::Int8, 1),
+        Mode::Uint8 => (SampleType::Uint8, 1),
+        Mode::Int16 => (SampleType::Int16, 1),
+        Mode::Uint16 => (SampleType::Uint16, 1),
+        Mode::Float32 => (SampleType::Float32, 1),
+        Mode::Int16Complex => (SampleType::Int16, 2),
+        Mode::Float32Complex => (SampleType::Float32, 2),
+        Mode::Float16 => {
+            let values = slice_to_f32(mode, slice_data, ComplexMapping::default())?;
+            return Ok(Samples::new(&values, width, height));
+        }
+        _ => anyhow::bail!("Unsupported MRC mode: {:?}", mode),
+    };
+    Ok(Samples {
+        data: slice_data.to_vec(),
+        dtype,
+        shape: [height as usize, width as usize, channels],
+    })
+}
+
+/// Convert raw slice data to one `f32` value per pixel.
+fn slice_to_f32(mode: Mode, slice_data: &[u8], complex: ComplexMapping) -> Result<Vec<f32>> {
+    Ok(match mode {
+        Mode::Int8 => slice_data.iter().map(|&val| val as i8 as f32).collect(),
+        Mode::Int16 => read_values::<i16>(slice_data)
+            .into_iter()
+            .map(|val| val as f32)
+            .collect(),
+        Mode::Uint8 => slice_data.iter().map(|&val| val as f32).collect(),
+        Mode::Uint16 => read_values::<u16>(slice_data)
+            .into_iter()
+            .map(|val| val as f32)
+            .collect(),
+        Mode::Float32 => read_values(slice_data),
+        Mode::Int16Complex | Mode::Float32Complex => complex_pairs(mode, slice_data)?
             .iter()
-            .map(|&val| ((val - min_val) / range * 255.0) as u8)
-            .collect()
+            .map(|&[real, imag]| complex.component(real, imag))
+            .collect(),
+        Mode::Float16 => {
+            // 16-bit half-precision float -> f32
+            read_values::<u16>(slice_data)
+                .into_iter()
+                .map(|bits| half::f16::from_bits(bits).to_f32())
+                .collect()
+        }
+        _ => anyhow::bail!("Unsupported MRC mode: {:?}", mode),
+    })
+}
+
+/// Read complex slice data as `[real, imaginary]` pairs.
+fn complex_pairs(mode: Mode, slice_data: &[u8]) -> Result<Vec<[f32; 2]>> {
+    Ok(match mode {
+        Mode::Int16Complex => read_values::<[i16; 2]>(slice_data)
+            .into_iter()
+            .map(|[real, imag]| [real as f32, imag as f32])
+            .collect(),
+        Mode::Float32Complex => read_values(slice_data),
+        _ => anyhow::bail!("MRC mode {:?} is not complex", mode),
+    })
+}
+
+/// Copy raw slice data into values of type `T`. The data is not necessarily aligned
+/// for `T`, as the extended header before it can have any size.
+fn read_values<T: bytemuck::Pod>(slice_data: &[u8]) -> Vec<T> {
+    slice_data
+        .chunks_exact(size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect()
+}
+
+/// Map complex values to RGB, with the phase as hue and the mapped log-magnitude as value.
+/// Returns the pixels and the log-magnitude range.
+fn map_phase_hue(
+    pairs: &[[f32; 2]],
+    width: u32,
+    height: u32,
+    mapping: &IntensityMapping,
+    metadata: &mut HashMap<String, MetadataValue>,
+) -> (Vec<u8>, (f32, f32)) {
+    let values: Vec<f32> = pairs
+        .iter()
+        .map(|&[real, imag]| ComplexMapping::PhaseHue.component(real, imag))
+        .collect();
+    let mapped = map_to_u8(&values, width as usize, height as usize, mapping);
+    mapped.add_metadata(mapping, metadata);
+    let rgb_data = pairs
+        .iter()
+        .zip(&mapped.data)
+        .flat_map(|(&[real, imag], &value)| phase_to_rgb(imag.atan2(real), value))
+        .collect();
+    (rgb_data, mapped.data_range)
+}
+
+/// Convert a phase angle (radians) to a fully saturated hue with the given value.
+fn phase_to_rgb(phase: f32, value: u8) -> [u8; 3] {
+    let hue = (phase.to_degrees() + 360.0) % 360.0 / 60.0;
+    let value = value as f32;
+    let falling = value * (1.0 - hue.fract());
+    let rising = value * hue.fract();
+    let (r, g, b) = match hue as u32 {
+        0 => (value, rising, 0.0),
+        1 => (falling, value, 0.0),
+        2 => (0.0, value, rising),
+        3 => (0.0, falling, value),
+        4 => (rising, 0.0, value),
+        _ => (value, 0.0, falling),
     };
-    Ok((ColorType::Grayscale, converted_data, (min_val, max_val)))
+    [r as u8, g as u8, b as u8]
 }
@@ -0,0 +1,243 @@
+use crate::metadata::{MetadataMap, MetadataValue};
+use crate::pixel_size::PixelSize;
+use crate::typ::{DecodedImage, SampleType};
+use anyhow::Result;
+use mrc::{Header, Mode};
+
+const MAX_LABELS: usize = 10;
+
+/// Encode `images` as the sections of an MRC 2014 file, at their original sample
+/// precision where MRC has a matching mode and as 32-bit floats otherwise. Unsigned
+/// 8-bit data is widened to unsigned 16-bit, as MRC 2014 has no mode for it.
+///
+/// The images must all be single-channel (or complex) with the same size and sample type.
+/// The cell size is derived from the pixel size; the origin, space group and labels of
+/// MRC input are carried over from `file_metadata`.
+pub fn encode_mrc(images: &[DecodedImage], file_metadata: Option<&MetadataMap>) -> Result<Vec<u8>> {
+    let Some(first) = images.first() else {
+        anyhow::bail!("No images to write");
+    };
+    let samples = images
+        .iter()
+        .map(|image| {
+            image.samples.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("Image {} has no samples to write", image.info.image_index)
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let first_samples = samples[0];
+    if let Some((image, mismatch)) = images
+        .iter()
+        .zip(&samples)
+        .find(|(_, s)| (s.dtype, s.shape) != (first_samples.dtype, first_samples.shape))
+    {
+        anyhow::bail!(
+            "All images of an MRC stack must match the first ({:?} {:?}), but image {} is {:?} {:?}",
+            first_samples.shape,
+            first_samples.dtype,
+            image.info.image_index,
+            mismatch.shape,
+            mismatch.dtype
+        );
+    }
+    let mode = match (first_samples.dtype, first_samples.shape[2]) {
+        (SampleType::Int8, 1) => Mode::Int8,
+        (SampleType::Int16, 1) => Mode::Int16,
+        (SampleType::Uint8 | SampleType::Uint16, 1) => Mode::Uint16,
+        (SampleType::Int16, 2) => Mode::Int16Complex,
+        (SampleType::Float32, 2) => Mode::Float32Complex,
+        (_, 1) => Mode::Float32,
+        (_, channels) => {
+            anyhow::bail!("MRC files can't store images with {channels} channels")
+        }
+    };
+
+    let section_len = first_samples.shape.iter().product::<usize>() * mode.byte_size();
+    let mut data = Vec::with_capacity(section_len * samples.len());
+    let mut statistics = Statistics::new();
+    for samples in &samples {
+        let values = samples.to_f64();
+        match (mode, samples.dtype) {
+            (Mode::Uint16, SampleType::Uint8) => {
+                data.extend(
+                    samples
+                        .data
+                        .iter()
+                        .flat_map(|&v| u16::from(v).to_le_bytes()),
+                );
+            }
+            (Mode::Float32, dtype) if dtype != SampleType::Float32 => {
+                data.extend(values.iter().flat_map(|&v| (v as f32).to_le_bytes()));
+            }
+            _ => data.extend_from_slice(&samples.data),
+        }
+        if samples.shape[2] == 2 {
+            // Complex data is described by its amplitudes.
+            for pair in values.chunks_exact(2) {
+                statistics.add(pair[0].hypot(pair[1]));
+            }
+        } else {
+            values.iter().for_each(|&v| statistics.add(v));
+        }
+    }
+
+    let header = build_header(first, images.len(), mode, &statistics, file_metadata);
+    let mut mrc_data = header_bytes(&header).to_vec();
+    mrc_data.extend(data);
+    Ok(mrc_data)
+}
+
+fn build_header(
+    first: &DecodedImage,
+    sections: usize,
+    mode: Mode,
+    statistics: &Statistics,
+    file_metadata: Option<&MetadataMap>,
+) -> Header {
+    let (nx, ny, nz) = (first.width as i32, first.height as i32, sections as i32);
+    // Pixel size in Å; 1 Å if unknown, as is customary.
+    let (size_x, size_y, size_z) = first
+        .info
+        .metadata
+        .as_ref()
+        .and_then(PixelSize::from_metadata)
+        .map(|size| {
+            let angstroms = size.unit.nanometers() * 10.0;
+            (
+                size.x * angstroms,
+                size.y * angstroms,
+                size.z.unwrap_or(size.x) * angstroms,
+            )
+        })
+        .unwrap_or((1.0, 1.0, 1.0));
+
+    let number = |key: &str| match file_metadata.and_then(|metadata| metadata.get(key)) {
+        Some(MetadataValue::Number(value)) => Some(*value),
+        Some(MetadataValue::Integer(value)) => Some(*value as f64),
+        _ => None,
+    };
+    let origin = ["origin_x", "origin_y", "origin_z"].map(|key| number(key).unwrap_or(0.0) as f32);
+    let ispg = number("ispg").map_or(0, |ispg| ispg as i32);
+
+    let mut labels = vec!["Written by obscura-image".to_string()];
+    labels.extend((0..MAX_LABELS).filter_map(|n| {
+        match file_metadata.and_then(|metadata| metadata.get(&format!("label_{n}"))) {
+            Some(MetadataValue::String(label)) => {
+                Some(label.trim_end_matches(['\0', ' ']).to_string())
+            }
+            _ => None,
+        }
+    }));
+    labels.truncate(MAX_LABELS);
+    let mut label = [b' '; 800];
+    for (slot, text) in label.chunks_exact_mut(80).zip(&labels) {
+        let text = truncate_utf8(text, 80);
+        slot[..text.len()].copy_from_slice(text.as_bytes());
+    }
+
+    // EXTTYP (bytes 8..12) stays empty as there is no extended header.
+    let mut extra = [0; 100];
+    extra[12..16].copy_from_slice(&20140i32.to_le_bytes());
+
+    let (dmin, dmax, dmean, rms) = statistics.finish();
+    Header {
+        nx,
+        ny,
+        nz,
+        mode: mode as i32,
+        nxstart: 0,
+        nystart: 0,
+        nzstart: 0,
+        mx: nx,
+        my: ny,
+        mz: nz,
+        xlen: (nx as f64 * size_x) as f32,
+        ylen: (ny as f64 * size_y) as f32,
+        zlen: (nz as f64 * size_z) as f32,
+        alpha: 90.0,
+        beta: 90.0,
+        gamma: 90.0,
+        mapc: 1,
+        mapr: 2,
+        maps: 3,
+        dmin,
+        dmax,
+        dmean,
+        ispg,
+        nsymbt: 0,
+        extra,
+        origin,
+        map: *b"MAP ",
+        // Little-endian
+        machst: [0x44, 0x44, 0, 0],
+        rms,
+        nlabl: labels.len() as i32,
+        label,
+    }
+}
+
+/// Density statistics, accumulated section by section.
+struct Statistics {
+    count: usize,
+    min: f64,
+    max: f64,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Statistics {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            sum_squares: 0.0,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.sum_squares += value * value;
+    }
+
+    /// Minimum, maximum, mean and RMS deviation from the mean.
+    fn finish(&self) -> (f32, f32, f32, f32) {
+        if self.count == 0 {
+            return (0.0, 0.0, 0.0, 0.0);
+        }
+        let count = self.count as f64;
+        let mean = self.sum / count;
+        // Clamped, as rounding can leave a tiny negative variance for constant data.
+        let variance = (self.sum_squares / count - mean * mean).max(0.0);
+        (
+            self.min as f32,
+            self.max as f32,
+            mean as f32,
+            variance.sqrt() as f32,
+        )
+    }
+}
+
+/// The longest prefix of `text` that fits in `len` bytes.
+fn truncate_utf8(text: &str, len: usize) -> &str {
+    let mut end = text.len().min(len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn header_bytes(header: &Header) -> &[u8] {
+    // The inverse of `read_header` in `mrc.rs`:
+    // * Header is #[repr(C)], 1024 bytes with no padding
+    // * Header fields are all plain data (integers and floats, no pointers)
+    // * The fields are written in native byte order, which is little-endian on
+    //   the targets we build for, as declared in `machst`
+    unsafe {
+        std::slice::from_raw_parts((header as *const Header).cast::<u8>(), size_of::<Header>())
+    }
+}
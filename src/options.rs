@@ -0,0 +1,483 @@
+use crate::intensity::IntensityMapping;
+use crate::metadata::MetadataMap;
+use crate::mrc::{ComplexMapping, StackMode};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Default cap for decompressed input, see [`DecodeOptions::max_decompressed_size`].
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 1 << 30;
+
+/// Selects the images (TIFF pages, MRC slices) `start..end` in steps of `step`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrameSelection {
+    pub start: usize,
+    /// Exclusive end; `None` means up to the last image.
+    pub end: Option<usize>,
+    pub step: usize,
+}
+
+impl Default for FrameSelection {
+    fn default() -> Self {
+        FrameSelection {
+            start: 0,
+            end: None,
+            step: 1,
+        }
+    }
+}
+
+impl FrameSelection {
+    /// The selected indices out of `count` images.
+    pub fn indices(&self, count: usize) -> impl Iterator<Item = usize> + use<> {
+        let end = self.end.map_or(count, |end| end.min(count));
+        (self.start..end).step_by(self.step.max(1))
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        index >= self.start
+            && self.end.is_none_or(|end| index < end)
+            && (index - self.start).is_multiple_of(self.step.max(1))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    /// Lossless WebP, usually smaller than PNG. 16-bit data is reduced to 8 bits.
+    Webp,
+    /// Baseline JPEG, see [`JpegOptions`]. Alpha is dropped and 16-bit data reduced to 8 bits.
+    Jpeg,
+    /// TIFF at the original sample precision, see [`TiffOptions`].
+    Tiff,
+    /// A single MRC 2014 file with all decoded images as its sections.
+    Mrc,
+    /// Uncompressed 8-bit RGBA pixels, row by row, ready for `ImageData`.
+    Rgba,
+    /// A single animated PNG of all decoded images, see [`ApngOptions`].
+    Apng,
+    /// No image data, e.g. when only the `samples` are of interest.
+    None,
+}
+
+impl OutputFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Mrc => "mrc",
+            OutputFormat::Rgba => "rgba",
+            OutputFormat::Apng => "apng",
+            OutputFormat::None => "none",
+        }
+    }
+}
+
+/// zlib compression effort for PNG output; `Best` gives the smallest files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    #[default]
+    Fast,
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngCompression::Fast => "fast",
+            PngCompression::Default => "default",
+            PngCompression::Best => "best",
+        }
+    }
+}
+
+/// Row filter for PNG output. `Adaptive` picks the best filter for each row,
+/// which usually gives smaller files at some cost in speed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngFilter {
+    None,
+    #[default]
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    Adaptive,
+}
+
+impl PngFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngFilter::None => "none",
+            PngFilter::Sub => "sub",
+            PngFilter::Up => "up",
+            PngFilter::Avg => "avg",
+            PngFilter::Paeth => "paeth",
+            PngFilter::Adaptive => "adaptive",
+        }
+    }
+}
+
+/// PNG encoder settings. The defaults favour speed, for previews.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    /// Embed the metadata as iTXt chunks, the pixel size as pHYs and the source ICC profile as iCCP.
+    pub embed_metadata: bool,
+}
+
+/// Animation settings for APNG output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApngOptions {
+    /// Time each frame is shown, in milliseconds.
+    pub delay_ms: u16,
+    /// How many times to play the animation; 0 loops forever.
+    pub plays: u32,
+}
+
+impl Default for ApngOptions {
+    fn default() -> Self {
+        ApngOptions {
+            delay_ms: 100,
+            plays: 0,
+        }
+    }
+}
+
+/// JPEG encoder settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JpegOptions {
+    /// 1 (smallest) to 100 (best).
+    pub quality: u8,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        JpegOptions { quality: 85 }
+    }
+}
+
+/// Compression for TIFF output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TiffCompression {
+    None,
+    #[default]
+    Lzw,
+    Deflate,
+}
+
+impl TiffCompression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TiffCompression::None => "none",
+            TiffCompression::Lzw => "lzw",
+            TiffCompression::Deflate => "deflate",
+        }
+    }
+}
+
+/// TIFF encoder settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TiffOptions {
+    pub compression: TiffCompression,
+    /// Write all decoded images as the pages of a single TIFF, instead of one TIFF each.
+    pub multipage: bool,
+}
+
+/// Resampling filter for thumbnails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleFilter {
+    /// Average of the covered source pixels; fast and free of ringing.
+    #[default]
+    Area,
+    /// Lanczos (a = 3); sharper, at some cost in speed.
+    Lanczos,
+}
+
+impl ResampleFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResampleFilter::Area => "area",
+            ResampleFilter::Lanczos => "lanczos",
+        }
+    }
+}
+
+/// Shrink images to fit within `max_width` x `max_height` before encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThumbnailOptions {
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub filter: ResampleFilter,
+}
+
+/// Tile all decoded images into a single overview image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContactSheetOptions {
+    /// Number of columns; roughly square if not given.
+    pub columns: Option<u32>,
+    /// Size of the square cell each image is shrunk to fit.
+    pub tile_size: u32,
+    /// Gap between and around the cells.
+    pub spacing: u32,
+    /// Draw the image index in the corner of each cell.
+    pub labels: bool,
+    pub filter: ResampleFilter,
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        ContactSheetOptions {
+            columns: None,
+            tile_size: 128,
+            spacing: 4,
+            labels: false,
+            filter: ResampleFilter::default(),
+        }
+    }
+}
+
+/// Built-in colormaps for single-channel data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamedColormap {
+    Viridis,
+    Magma,
+    Inferno,
+    Cividis,
+    Hot,
+    Fire,
+    /// Diverging, from red through white to blue.
+    RdBu,
+}
+
+impl NamedColormap {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NamedColormap::Viridis => "viridis",
+            NamedColormap::Magma => "magma",
+            NamedColormap::Inferno => "inferno",
+            NamedColormap::Cividis => "cividis",
+            NamedColormap::Hot => "hot",
+            NamedColormap::Fire => "fire",
+            NamedColormap::RdBu => "rdbu",
+        }
+    }
+}
+
+/// Colormap for single-channel output: a built-in one by name, or a custom lookup table
+/// of `[r, g, b]` colors from low to high, interpolated to 256 entries.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Colormap {
+    Named(NamedColormap),
+    Custom(Vec<[u8; 3]>),
+}
+
+impl Colormap {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colormap::Named(colormap) => colormap.name(),
+            Colormap::Custom(_) => "custom",
+        }
+    }
+}
+
+/// How much metadata to return.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataLevel {
+    /// No metadata at all.
+    None,
+    /// Derived information (format, pixel size, value ranges, warnings, ...)
+    /// but no raw header dumps.
+    Basic,
+    /// Everything.
+    #[default]
+    Full,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DecodeOptions {
+    /// Intensity mapping for single-channel data.
+    /// `None` keeps each decoder's own default conversion.
+    pub intensity: Option<IntensityMapping>,
+    /// How complex-valued data is turned into a displayable image.
+    pub complex: ComplexMapping,
+    /// Which pages or slices to decode.
+    pub frames: FrameSelection,
+    /// Whether MRC slices are decoded individually, tiled or averaged.
+    pub stack: StackMode,
+    /// Maximum size in bytes compressed input may inflate to.
+    pub max_decompressed_size: usize,
+    /// Images wider than this are reported as errors instead of being decoded.
+    pub max_width: Option<u32>,
+    /// Images taller than this are reported as errors instead of being decoded.
+    pub max_height: Option<u32>,
+    pub output: OutputFormat,
+    /// PNG encoder settings, also used for APNG output.
+    pub png: PngOptions,
+    pub apng: ApngOptions,
+    pub jpeg: JpegOptions,
+    pub tiff: TiffOptions,
+    /// Output thumbnails instead of full-size images. Thumbnails carry no samples,
+    /// so TIFF output is written from the displayed data and MRC output is refused.
+    pub thumbnail: Option<ThumbnailOptions>,
+    /// Map single-channel images through a colormap to RGB. TIFF and MRC output is
+    /// written from the original samples, so it can only be colored as a contact sheet.
+    pub colormap: Option<Colormap>,
+    /// Output a single contact sheet of all decoded images, within `max_width` x
+    /// `max_height`. The sheet has no samples, so MRC output is refused.
+    pub contact_sheet: Option<ContactSheetOptions>,
+    /// Keep 16-bit data (TIFF Gray16/RGB16/RGBA16, 16-bit integer MRC) at 16 bits
+    /// in PNG output, instead of reducing it to 8 bits.
+    pub preserve_depth: bool,
+    /// Also return the original pixel values at full precision. Not available for
+    /// outputs that combine all images into one (APNG, MRC, multipage TIFF).
+    pub samples: bool,
+    pub metadata: MetadataLevel,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            intensity: None,
+            complex: ComplexMapping::default(),
+            frames: FrameSelection::default(),
+            stack: StackMode::default(),
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+            max_width: None,
+            max_height: None,
+            output: OutputFormat::default(),
+            png: PngOptions::default(),
+            apng: ApngOptions::default(),
+            jpeg: JpegOptions::default(),
+            tiff: TiffOptions::default(),
+            thumbnail: None,
+            colormap: None,
+            contact_sheet: None,
+            preserve_depth: false,
+            samples: false,
+            metadata: MetadataLevel::default(),
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// The output format and encoder settings, as reported in the output.
+    pub fn encoding_metadata(&self) -> MetadataMap {
+        let mut encoding = MetadataMap::from([("format".to_string(), self.output.name().into())]);
+        if matches!(self.output, OutputFormat::Png | OutputFormat::Apng) {
+            encoding.extend([
+                (
+                    "compression".to_string(),
+                    self.png.compression.name().into(),
+                ),
+                ("filter".to_string(), self.png.filter.name().into()),
+                ("embed_metadata".to_string(), self.png.embed_metadata.into()),
+                ("preserve_depth".to_string(), self.preserve_depth.into()),
+            ]);
+        }
+        if let Some(colormap) = &self.colormap {
+            encoding.insert("colormap".to_string(), colormap.name().into());
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            encoding.insert(
+                "thumbnail_filter".to_string(),
+                thumbnail.filter.name().into(),
+            );
+        }
+        if self.output == OutputFormat::Jpeg {
+            encoding.insert("quality".to_string(), self.jpeg.quality.into());
+        }
+        if self.output == OutputFormat::Tiff {
+            encoding.extend([
+                (
+                    "compression".to_string(),
+                    self.tiff.compression.name().into(),
+                ),
+                ("multipage".to_string(), self.tiff.multipage.into()),
+            ]);
+        }
+        if self.output == OutputFormat::Apng {
+            encoding.extend([
+                ("delay_ms".to_string(), self.apng.delay_ms.into()),
+                ("plays".to_string(), self.apng.plays.into()),
+            ]);
+        }
+        encoding
+    }
+
+    /// Whether decoders should keep the samples, either because they were asked
+    /// for or because the output format is written from them.
+    pub fn decode_samples(&self) -> bool {
+        self.samples || matches!(self.output, OutputFormat::Tiff | OutputFormat::Mrc)
+    }
+
+    /// Whether all decoded images are encoded into a single output image.
+    pub fn combine_images(&self) -> bool {
+        matches!(self.output, OutputFormat::Apng | OutputFormat::Mrc)
+            || (self.output == OutputFormat::Tiff && self.tiff.multipage)
+    }
+
+    /// Reject combinations of options that can't be honoured.
+    pub fn validate(&self) -> Result<()> {
+        if self.output == OutputFormat::Jpeg && !(1..=100).contains(&self.jpeg.quality) {
+            anyhow::bail!(
+                "JPEG quality must be between 1 and 100, not {}",
+                self.jpeg.quality
+            );
+        }
+        if self.output == OutputFormat::Mrc && self.thumbnail.is_some() {
+            anyhow::bail!(
+                "MRC output is written from the original samples and can't be a thumbnail"
+            );
+        }
+        if self.output == OutputFormat::Mrc && self.contact_sheet.is_some() {
+            anyhow::bail!(
+                "MRC output is written from the original samples and can't be a contact sheet"
+            );
+        }
+        // Contact sheets have no samples, so their tiles are written colored.
+        if self.colormap.is_some()
+            && matches!(self.output, OutputFormat::Tiff | OutputFormat::Mrc)
+            && self.contact_sheet.is_none()
+        {
+            anyhow::bail!(
+                "{} output is written from the original samples and can't be colored",
+                self.output.name().to_uppercase()
+            );
+        }
+        Ok(())
+    }
+
+    /// Refuse to decode images larger than `max_width` x `max_height`.
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<()> {
+        if self.max_width.is_some_and(|max| width > max)
+            || self.max_height.is_some_and(|max| height > max)
+        {
+            anyhow::bail!(
+                "Image size {width}x{height} exceeds the maximum of {}x{}",
+                self.max_width.map_or("any".to_string(), |w| w.to_string()),
+                self.max_height.map_or("any".to_string(), |h| h.to_string()),
+            );
+        }
+        Ok(())
+    }
+}
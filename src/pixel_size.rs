@@ -0,0 +1,93 @@
+use crate::metadata::{MetadataMap, MetadataValue};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthUnit {
+    Angstrom,
+    Nanometer,
+    Micrometer,
+    Inch,
+}
+
+impl LengthUnit {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LengthUnit::Angstrom => "Å",
+            LengthUnit::Nanometer => "nm",
+            LengthUnit::Micrometer => "µm",
+            LengthUnit::Inch => "inch",
+        }
+    }
+
+    /// Length of one unit in nanometers.
+    pub fn nanometers(&self) -> f64 {
+        match self {
+            LengthUnit::Angstrom => 0.1,
+            LengthUnit::Nanometer => 1.0,
+            LengthUnit::Micrometer => 1e3,
+            LengthUnit::Inch => 2.54e7,
+        }
+    }
+
+    /// Parse a unit name as found in OME-XML or ImageJ metadata, returning the unit
+    /// to report the size in and the factor to convert the size to it.
+    pub fn parse(name: &str) -> Option<(Self, f64)> {
+        Some(match name.trim() {
+            "\u{C5}" | "\u{212B}" | "A" | "angstrom" | "Angstrom" => (LengthUnit::Angstrom, 1.0),
+            "nm" | "nanometer" => (LengthUnit::Nanometer, 1.0),
+            "\u{B5}m" | "\u{3BC}m" | "\\u00B5m" | "um" | "micron" | "microns" | "micrometer" => {
+                (LengthUnit::Micrometer, 1.0)
+            }
+            "mm" | "millimeter" => (LengthUnit::Micrometer, 1e3),
+            "cm" | "centimeter" => (LengthUnit::Micrometer, 1e4),
+            "inch" | "in" => (LengthUnit::Inch, 1.0),
+            _ => return None,
+        })
+    }
+}
+
+/// Physical size of a pixel (or voxel, if `z` is known).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelSize {
+    pub x: f64,
+    pub y: f64,
+    pub z: Option<f64>,
+    pub unit: LengthUnit,
+    /// Where the size was derived from, e.g. `"tiff_resolution"`.
+    pub source: &'static str,
+}
+
+impl PixelSize {
+    pub fn add_metadata(&self, metadata: &mut MetadataMap) {
+        metadata.insert("pixel_size_x".to_string(), MetadataValue::from(self.x));
+        metadata.insert("pixel_size_y".to_string(), MetadataValue::from(self.y));
+        if let Some(z) = self.z {
+            metadata.insert("pixel_size_z".to_string(), MetadataValue::from(z));
+        }
+        metadata.insert("pixel_size_unit".to_string(), self.unit.symbol().into());
+        metadata.insert("pixel_size_source".to_string(), self.source.into());
+    }
+
+    /// Read back a pixel size stored with [`PixelSize::add_metadata`].
+    pub fn from_metadata(metadata: &MetadataMap) -> Option<Self> {
+        let MetadataValue::String(unit) = metadata.get("pixel_size_unit")? else {
+            return None;
+        };
+        let (unit, factor) = LengthUnit::parse(unit)?;
+        let size = |key: &str| match metadata.get(key) {
+            Some(MetadataValue::Number(size)) => positive(size * factor),
+            _ => None,
+        };
+        Some(PixelSize {
+            x: size("pixel_size_x")?,
+            y: size("pixel_size_y")?,
+            z: size("pixel_size_z"),
+            unit,
+            source: "metadata",
+        })
+    }
+}
+
+/// Return `value` if it is a usable (finite, positive) size.
+pub fn positive(value: f64) -> Option<f64> {
+    (value.is_finite() && value > 0.0).then_some(value)
+}
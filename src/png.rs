@@ -1,19 +1,147 @@
+use crate::metadata::{MetadataMap, MetadataValue};
+use crate::options::{ApngOptions, PngCompression, PngFilter, PngOptions};
+use crate::pixel_size::PixelSize;
 use crate::typ::DecodedImage;
 use anyhow::Result;
-use png::{BitDepth, Compression, Encoder};
-use std::io::Cursor;
+use png::{
+    AdaptiveFilterType, ColorType, Compression, Encoder, FilterType, Info, PixelDimensions, Unit,
+};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{Cursor, Write};
 
-pub fn encode_png(raw_image_data: &DecodedImage) -> Result<Vec<u8>> {
+/// Encode `raw_image_data` as PNG. With `options.embed_metadata`, the file and image
+/// metadata are written as iTXt chunks, the pixel size as pHYs and any ICC profile as iCCP.
+pub fn encode_png(
+    raw_image_data: &DecodedImage,
+    options: &PngOptions,
+    file_metadata: Option<&MetadataMap>,
+) -> Result<Vec<u8>> {
     let mut png_data = Vec::new();
     {
         let cursor = Cursor::new(&mut png_data);
-        let mut encoder = Encoder::new(cursor, raw_image_data.width, raw_image_data.height);
-        encoder.set_color(raw_image_data.color_type);
-        encoder.set_depth(BitDepth::Eight);
-        encoder.set_compression(Compression::Fast);
-
+        let encoder = png_encoder(cursor, raw_image_data, options, file_metadata)?;
         let mut writer = encoder.write_header()?;
         writer.write_image_data(&raw_image_data.data)?;
     }
     Ok(png_data)
 }
+
+/// Encode `frames` as an animated PNG. All frames must have the same size and pixel format;
+/// the metadata of the first frame is embedded if requested.
+pub fn encode_apng(
+    frames: &[DecodedImage],
+    options: &PngOptions,
+    animation: &ApngOptions,
+    file_metadata: Option<&MetadataMap>,
+) -> Result<Vec<u8>> {
+    let Some(first) = frames.first() else {
+        anyhow::bail!("No frames to animate");
+    };
+    if let Some(frame) = frames.iter().find(|frame| {
+        (frame.width, frame.height, frame.color_type, frame.depth)
+            != (first.width, first.height, first.color_type, first.depth)
+    }) {
+        anyhow::bail!(
+            "All frames of an animation must match the first ({}x{} {}-bit {:?}), but image {} is {}x{} {}-bit {:?}",
+            first.width,
+            first.height,
+            first.depth as u8,
+            first.color_type,
+            frame.info.image_index,
+            frame.width,
+            frame.height,
+            frame.depth as u8,
+            frame.color_type
+        );
+    }
+
+    let mut png_data = Vec::new();
+    {
+        let cursor = Cursor::new(&mut png_data);
+        let mut encoder = png_encoder(cursor, first, options, file_metadata)?;
+        encoder.set_animated(frames.len() as u32, animation.plays)?;
+        encoder.set_frame_delay(animation.delay_ms, 1000)?;
+        let mut writer = encoder.write_header()?;
+        for frame in frames {
+            writer.write_image_data(&frame.data)?;
+        }
+    }
+    Ok(png_data)
+}
+
+/// Set up a PNG encoder for images like `image`, with the given settings and metadata.
+fn png_encoder<'a, W: Write>(
+    writer: W,
+    image: &'a DecodedImage,
+    options: &PngOptions,
+    file_metadata: Option<&MetadataMap>,
+) -> Result<Encoder<'a, W>> {
+    let mut info = Info::with_size(image.width, image.height);
+    if options.embed_metadata {
+        info.pixel_dims = image.info.metadata.as_ref().and_then(pixel_dimensions);
+        info.icc_profile = image
+            .icc_profile
+            .as_deref()
+            .filter(|profile| icc_matches(profile, image.color_type))
+            .map(Cow::Borrowed);
+    }
+
+    let mut encoder = Encoder::with_info(writer, info)?;
+    encoder.set_color(image.color_type);
+    encoder.set_depth(image.depth);
+    encoder.set_compression(match options.compression {
+        PngCompression::Fast => Compression::Fast,
+        PngCompression::Default => Compression::Default,
+        PngCompression::Best => Compression::Best,
+    });
+    match options.filter {
+        PngFilter::Adaptive => encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive),
+        filter => encoder.set_filter(match filter {
+            PngFilter::None => FilterType::NoFilter,
+            PngFilter::Up => FilterType::Up,
+            PngFilter::Avg => FilterType::Avg,
+            PngFilter::Paeth => FilterType::Paeth,
+            _ => FilterType::Sub,
+        }),
+    }
+    if options.embed_metadata {
+        // Image metadata takes precedence over file metadata with the same key.
+        let text: BTreeMap<&String, &MetadataValue> = file_metadata
+            .into_iter()
+            .chain(image.info.metadata.as_ref())
+            .flatten()
+            .collect();
+        for (key, value) in text {
+            // Keywords are limited to 79 bytes.
+            if (1..80).contains(&key.len()) {
+                encoder.add_itxt_chunk(key.clone(), value.to_string())?;
+            }
+        }
+    }
+    Ok(encoder)
+}
+
+/// Pixels per meter from the pixel size metadata, if it fits the pHYs chunk.
+fn pixel_dimensions(metadata: &MetadataMap) -> Option<PixelDimensions> {
+    let size = PixelSize::from_metadata(metadata)?;
+    let per_meter = |length: f64| {
+        let ppm = (1e9 / (length * size.unit.nanometers())).round();
+        (ppm >= 1.0 && ppm <= u32::MAX as f64).then_some(ppm as u32)
+    };
+    Some(PixelDimensions {
+        xppu: per_meter(size.x)?,
+        yppu: per_meter(size.y)?,
+        unit: Unit::Meter,
+    })
+}
+
+/// Whether an ICC profile's color space (bytes 16..20 of its header) suits `color_type`.
+pub(crate) fn icc_matches(profile: &[u8], color_type: ColorType) -> bool {
+    let space = profile.get(16..20);
+    match color_type {
+        ColorType::Grayscale | ColorType::GrayscaleAlpha => space == Some(b"GRAY"),
+        ColorType::Rgb | ColorType::Rgba => space == Some(b"RGB "),
+        ColorType::Indexed => false,
+    }
+}
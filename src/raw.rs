@@ -0,0 +1,58 @@
+use crate::typ::DecodedImage;
+use anyhow::Result;
+use png::{BitDepth, ColorType};
+use std::borrow::Cow;
+
+#[inline]
+pub(crate) fn convert_16_to_8(value: u16) -> u8 {
+    (value.saturating_add(128) >> 8) as u8
+}
+
+/// 16-bit samples in the big-endian byte order used by PNG.
+pub(crate) fn to_be_bytes(values: &[u16]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+/// Convert an image to 8-bit RGBA, as expected by `ImageData` in the browser.
+pub fn to_rgba8(image: &DecodedImage) -> Result<Vec<u8>> {
+    let data = to_8bit(image)?;
+    let mut rgba = Vec::with_capacity(image.width as usize * image.height as usize * 4);
+    match image.color_type {
+        ColorType::Grayscale => {
+            for &gray in data.iter() {
+                rgba.extend_from_slice(&[gray, gray, gray, 255]);
+            }
+        }
+        ColorType::GrayscaleAlpha => {
+            for pixel in data.chunks_exact(2) {
+                rgba.extend_from_slice(&[pixel[0], pixel[0], pixel[0], pixel[1]]);
+            }
+        }
+        ColorType::Rgb => {
+            for pixel in data.chunks_exact(3) {
+                rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+            }
+        }
+        ColorType::Rgba => rgba.extend_from_slice(&data),
+        ColorType::Indexed => anyhow::bail!("Indexed images can't be converted to RGBA"),
+    }
+    Ok(rgba)
+}
+
+/// The image data with 16-bit samples reduced to 8 bits, for 8-bit-only encoders.
+pub(crate) fn to_8bit(image: &DecodedImage) -> Result<Cow<'_, [u8]>> {
+    Ok(match image.depth {
+        BitDepth::Eight => Cow::Borrowed(&image.data),
+        BitDepth::Sixteen => Cow::Owned(
+            image
+                .data
+                .chunks_exact(2)
+                .map(|bytes| convert_16_to_8(u16::from_be_bytes([bytes[0], bytes[1]])))
+                .collect(),
+        ),
+        depth => anyhow::bail!("Unsupported bit depth: {depth:?}"),
+    })
+}
@@ -0,0 +1,232 @@
+use crate::metadata::MetadataValue;
+use crate::options::{ResampleFilter, ThumbnailOptions};
+use crate::typ::DecodedImage;
+use anyhow::Result;
+use png::{BitDepth, ColorType};
+use std::collections::VecDeque;
+
+/// Shrink `image` to fit within the thumbnail bounds, keeping its aspect ratio.
+/// Images that already fit are returned as they are. Thumbnails carry no samples.
+pub fn thumbnail(image: DecodedImage, options: &ThumbnailOptions) -> Result<DecodedImage> {
+    let (width, height) = fit_within(
+        image.width,
+        image.height,
+        options.max_width,
+        options.max_height,
+    );
+    if (width, height) == (image.width, image.height) {
+        return Ok(image);
+    }
+    let (original_width, original_height) = (image.width, image.height);
+    let mut thumbnail = resize(image, width, height, options.filter)?;
+    if let Some(metadata) = thumbnail.info.metadata.as_mut() {
+        metadata.insert("original_width".to_string(), original_width.into());
+        metadata.insert("original_height".to_string(), original_height.into());
+        // The pixels are larger now.
+        for (key, scale) in [
+            ("pixel_size_x", original_width as f64 / width as f64),
+            ("pixel_size_y", original_height as f64 / height as f64),
+        ] {
+            if let Some(MetadataValue::Number(size)) = metadata.get_mut(key) {
+                *size *= scale;
+            }
+        }
+    }
+    Ok(thumbnail)
+}
+
+/// The largest size with the aspect ratio of `width` x `height` that fits the bounds,
+/// never larger than the original.
+pub fn fit_within(
+    width: u32,
+    height: u32,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+) -> (u32, u32) {
+    let scale = [
+        max_width.map(|max| max as f64 / width as f64),
+        max_height.map(|max| max as f64 / height as f64),
+    ]
+    .into_iter()
+    .flatten()
+    .fold(1.0, f64::min);
+    if scale >= 1.0 {
+        return (width, height);
+    }
+    let scaled = |size: u32| ((size as f64 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// Resample `image` to `width` x `height`. Color is weighted by alpha, so transparent
+/// pixels don't bleed into their neighbours.
+pub fn resize(
+    mut image: DecodedImage,
+    width: u32,
+    height: u32,
+    filter: ResampleFilter,
+) -> Result<DecodedImage> {
+    let channels = match image.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        ColorType::Indexed => anyhow::bail!("Indexed images can't be resized"),
+    };
+    let has_alpha = channels % 2 == 0;
+    let max = match image.depth {
+        BitDepth::Eight => u8::MAX as f32,
+        BitDepth::Sixteen => u16::MAX as f32,
+        depth => anyhow::bail!("Unsupported bit depth: {depth:?}"),
+    };
+
+    let (src_width, src_height) = (image.width as usize, image.height as usize);
+    let (dst_width, dst_height) = (width as usize, height as usize);
+    let row_len = dst_width * channels;
+    let row_weights = weights(src_width, dst_width, filter);
+
+    // Resample rows, then columns. Source rows are resampled as they are first needed
+    // and kept only while later destination rows still draw from them.
+    let mut pixels = vec![0.0; src_width * channels];
+    let mut resample_row = |y: usize| {
+        read_row(&image.data, image.depth, y, &mut pixels);
+        if has_alpha {
+            for pixel in pixels.chunks_exact_mut(channels) {
+                let alpha = pixel[channels - 1] / max;
+                for value in &mut pixel[..channels - 1] {
+                    *value *= alpha;
+                }
+            }
+        }
+        let mut row = vec![0.0; row_len];
+        for (x, (start, weights)) in row_weights.iter().enumerate() {
+            for (i, weight) in weights.iter().enumerate() {
+                let offset = (start + i) * channels;
+                for c in 0..channels {
+                    row[x * channels + c] += weight * pixels[offset + c];
+                }
+            }
+        }
+        row
+    };
+
+    let sample_size = if image.depth == BitDepth::Eight { 1 } else { 2 };
+    let mut data = Vec::with_capacity(row_len * dst_height * sample_size);
+    let mut rows: VecDeque<(usize, Vec<f32>)> = VecDeque::new();
+    let mut resized = vec![0.0; row_len];
+    let quantize = |value: f32| value.round().clamp(0.0, max);
+    for (start, weights) in weights(src_height, dst_height, filter) {
+        while rows.front().is_some_and(|&(y, _)| y < start) {
+            rows.pop_front();
+        }
+        resized.fill(0.0);
+        for (y, weight) in (start..).zip(&weights) {
+            let row = match rows.iter().position(|&(cached, _)| cached == y) {
+                Some(index) => &rows[index].1,
+                None => {
+                    rows.push_back((y, resample_row(y)));
+                    &rows.back().unwrap().1
+                }
+            };
+            for (out, value) in resized.iter_mut().zip(row) {
+                *out += weight * value;
+            }
+        }
+
+        if has_alpha {
+            for pixel in resized.chunks_exact_mut(channels) {
+                let alpha = pixel[channels - 1].clamp(0.0, max) / max;
+                for value in &mut pixel[..channels - 1] {
+                    *value = if alpha > 0.0 { *value / alpha } else { 0.0 };
+                }
+            }
+        }
+        match image.depth {
+            BitDepth::Eight => data.extend(resized.iter().map(|&v| quantize(v) as u8)),
+            _ => data.extend(
+                resized
+                    .iter()
+                    .flat_map(|&v| (quantize(v) as u16).to_be_bytes()),
+            ),
+        }
+    }
+    image.data = data;
+    image.width = width;
+    image.height = height;
+    image.info.width = width;
+    image.info.height = height;
+    image.samples = None;
+    Ok(image)
+}
+
+/// Read row `y` of 8 or 16-bit `data` into `row`.
+fn read_row(data: &[u8], depth: BitDepth, y: usize, row: &mut [f32]) {
+    let len = row.len();
+    match depth {
+        BitDepth::Eight => {
+            for (value, &byte) in row.iter_mut().zip(&data[y * len..(y + 1) * len]) {
+                *value = byte as f32;
+            }
+        }
+        _ => {
+            let bytes = &data[y * len * 2..(y + 1) * len * 2];
+            for (value, bytes) in row.iter_mut().zip(bytes.chunks_exact(2)) {
+                *value = u16::from_be_bytes([bytes[0], bytes[1]]) as f32;
+            }
+        }
+    }
+}
+
+/// For each destination pixel, the first source pixel it draws from and the normalized
+/// weights of that and the following source pixels.
+fn weights(src: usize, dst: usize, filter: ResampleFilter) -> Vec<(usize, Vec<f32>)> {
+    let scale = src as f64 / dst as f64;
+    (0..dst)
+        .map(|i| {
+            let (start, weights): (usize, Vec<f64>) = match filter {
+                ResampleFilter::Area => {
+                    // The fraction of each source pixel covered by the destination pixel.
+                    let (low, high) = (i as f64 * scale, (i + 1) as f64 * scale);
+                    let start = low.floor() as usize;
+                    let end = (high.ceil() as usize).min(src);
+                    let weights = (start..end)
+                        .map(|j| high.min((j + 1) as f64) - low.max(j as f64))
+                        .collect();
+                    (start, weights)
+                }
+                ResampleFilter::Lanczos => {
+                    // Stretch the kernel when downscaling, so it covers all source pixels.
+                    let stretch = scale.max(1.0);
+                    let center = (i as f64 + 0.5) * scale;
+                    let start = (center - 3.0 * stretch).floor().max(0.0) as usize;
+                    let end = ((center + 3.0 * stretch).ceil() as usize).min(src);
+                    let weights = (start..end)
+                        .map(|j| lanczos3((j as f64 + 0.5 - center) / stretch))
+                        .collect();
+                    (start, weights)
+                }
+            };
+            let sum: f64 = weights.iter().sum();
+            if sum.abs() < f64::EPSILON {
+                // Fall back to the nearest pixel.
+                let nearest = (((i as f64 + 0.5) * scale) as usize).min(src - 1);
+                return (nearest, vec![1.0]);
+            }
+            (start, weights.iter().map(|w| (w / sum) as f32).collect())
+        })
+        .collect()
+}
+
+fn lanczos3(x: f64) -> f64 {
+    if x.abs() >= 3.0 {
+        return 0.0;
+    }
+    sinc(x) * sinc(x / 3.0)
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        return 1.0;
+    }
+    let x = x * std::f64::consts::PI;
+    x.sin() / x
+}
@@ -1,40 +1,235 @@
-use crate::typ::{DecodeResult, DecodedImage, ImageDecodeError, ImageInfo};
+use crate::format::FormatDecoder;
+use crate::intensity::{IntensityMapping, map_to_u8};
+use crate::metadata::{MetadataMap, MetadataValue};
+use crate::options::{DecodeOptions, MetadataLevel};
+use crate::pixel_size::{LengthUnit, PixelSize, positive};
+use crate::raw::{convert_16_to_8, to_be_bytes};
+use crate::typ::{DecodeResult, DecodedImage, ImageDecodeError, ImageInfo, ProbeResult, Samples};
 use anyhow::Result;
+use png::BitDepth;
+use std::collections::HashMap;
 use std::io::Cursor;
 use tiff::{
     ColorType,
-    decoder::{Decoder, DecodingResult},
+    decoder::{Decoder, DecodingResult, ifd::Value},
+    tags::Tag,
 };
 
-#[inline]
-fn convert_16_to_8(value: u16) -> u8 {
-    (value.saturating_add(128) >> 8) as u8
+/// Map single-channel samples through `mapping`, recording the mapping in `metadata`.
+fn map_gray(
+    values: Vec<f32>,
+    width: u32,
+    height: u32,
+    mapping: &IntensityMapping,
+    metadata: &mut MetadataMap,
+) -> Vec<u8> {
+    let mapped = map_to_u8(&values, width as usize, height as usize, mapping);
+    mapped.add_metadata(mapping, metadata);
+    mapped.data
+}
+
+fn find_tag_f64(decoder: &mut Decoder<Cursor<&[u8]>>, tag: Tag) -> Option<f64> {
+    match decoder.find_tag(tag).ok()?? {
+        Value::Rational(n, d) => Some(n as f64 / d as f64),
+        Value::RationalBig(n, d) => Some(n as f64 / d as f64),
+        Value::Float(v) => Some(v as f64),
+        Value::Double(v) => Some(v),
+        Value::Short(v) => Some(v as f64),
+        Value::Unsigned(v) => Some(v as f64),
+        _ => None,
+    }
+}
+
+/// Find the value of attribute `name` in an XML document, without a full XML parser.
+fn xml_attribute<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!(" {name}=\""))? + name.len() + 3;
+    let length = xml[start..].find('"')?;
+    Some(&xml[start..start + length])
+}
+
+/// Pixel size from OME-XML `<Pixels PhysicalSizeX=... PhysicalSizeXUnit=...>` attributes.
+fn ome_pixel_size(description: &str) -> Option<PixelSize> {
+    if !description.contains("<OME") {
+        return None;
+    }
+    let axis = |axis: &str| {
+        let size: f64 = xml_attribute(description, &format!("PhysicalSize{axis}"))?
+            .parse()
+            .ok()?;
+        let unit = xml_attribute(description, &format!("PhysicalSize{axis}Unit")).unwrap_or("µm");
+        let (unit, factor) = LengthUnit::parse(unit)?;
+        Some((positive(size * factor)?, unit))
+    };
+    let (x, unit) = axis("X")?;
+    // Express the other axes in the unit of the X axis.
+    let in_x_unit =
+        |(size, axis_unit): (f64, LengthUnit)| size * axis_unit.nanometers() / unit.nanometers();
+    Some(PixelSize {
+        x,
+        y: axis("Y").map(in_x_unit)?,
+        z: axis("Z").map(in_x_unit),
+        unit,
+        source: "ome",
+    })
+}
+
+/// Look up `key=value` lines in an ImageJ image description.
+fn imagej_property<'a>(description: &'a str, key: &str) -> Option<&'a str> {
+    description
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Pixel size from OME-XML or ImageJ metadata, or the XResolution/YResolution tags.
+fn read_pixel_size(decoder: &mut Decoder<Cursor<&[u8]>>) -> Option<PixelSize> {
+    let description = decoder
+        .find_tag(Tag::ImageDescription)
+        .ok()
+        .flatten()
+        .and_then(|value| value.into_string().ok());
+    if let Some(size) = description.as_deref().and_then(ome_pixel_size) {
+        return Some(size);
+    }
+
+    // Resolutions are given in pixels per unit.
+    let x_resolution = find_tag_f64(decoder, Tag::XResolution).and_then(positive)?;
+    let y_resolution = find_tag_f64(decoder, Tag::YResolution)
+        .and_then(positive)
+        .unwrap_or(x_resolution);
+
+    // ImageJ stores its calibration unit in the description, with the resolution tags in that unit.
+    if let Some(description) = description.filter(|d| d.starts_with("ImageJ="))
+        && let Some((unit, factor)) =
+            imagej_property(&description, "unit").and_then(LengthUnit::parse)
+    {
+        let spacing = imagej_property(&description, "spacing").and_then(|s| s.parse().ok());
+        return Some(PixelSize {
+            x: factor / x_resolution,
+            y: factor / y_resolution,
+            z: spacing.and_then(|z: f64| positive(z * factor)),
+            unit,
+            source: "imagej",
+        });
+    }
+
+    // ResolutionUnit: 1 = none, 2 = inch (the default), 3 = centimeter
+    let (unit, factor) = match decoder.find_tag_unsigned::<u16>(Tag::ResolutionUnit) {
+        Ok(Some(1)) => return None,
+        Ok(Some(3)) => (LengthUnit::Micrometer, 1e4),
+        _ => (LengthUnit::Inch, 1.0),
+    };
+    Some(PixelSize {
+        x: factor / x_resolution,
+        y: factor / y_resolution,
+        z: None,
+        unit,
+        source: "tiff_resolution",
+    })
+}
+
+pub struct TiffFormat;
+
+impl FormatDecoder for TiffFormat {
+    fn name(&self) -> &'static str {
+        "tiff"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tif", "tiff"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["image/tiff"]
+    }
+
+    fn probe(&self, data: &[u8]) -> bool {
+        is_tiff(data)
+    }
+
+    fn decode(&self, data: &[u8], options: &DecodeOptions) -> Result<DecodeResult> {
+        decode_tiff_with_options(data, options)
+    }
+
+    fn read_info(&self, data: &[u8], options: &DecodeOptions) -> Result<ProbeResult> {
+        read_tiff_info(data, options)
+    }
+}
+
+/// Whether `data` starts with a classic or BigTIFF signature, in either byte order.
+pub fn is_tiff(data: &[u8]) -> bool {
+    [b"II*\0", b"MM\0*", b"II+\0", b"MM\0+"]
+        .iter()
+        .any(|magic| data.starts_with(*magic))
 }
 
 pub fn decode_tiff(tiff_data: &[u8]) -> Result<DecodeResult> {
+    decode_tiff_with_options(tiff_data, &DecodeOptions::default())
+}
+
+pub fn decode_tiff_with_options(tiff_data: &[u8], options: &DecodeOptions) -> Result<DecodeResult> {
+    let (images, errors) = for_each_image(tiff_data, options, |decoder, image_index| {
+        decode_single_image(decoder, image_index, options)
+    })?;
+    Ok(DecodeResult {
+        images,
+        errors,
+        metadata: None,
+    })
+}
+
+/// Read the dimensions, color types and pixel sizes of the selected pages without decoding them.
+pub fn read_tiff_info(tiff_data: &[u8], options: &DecodeOptions) -> Result<ProbeResult> {
+    let (images, errors) = for_each_image(tiff_data, options, |decoder, image_index| {
+        let (width, height) = decoder.dimensions()?;
+        options.check_dimensions(width, height)?;
+        let colortype = decoder.colortype()?;
+        let mut metadata = HashMap::new();
+        if let Some(pixel_size) = read_pixel_size(decoder) {
+            pixel_size.add_metadata(&mut metadata);
+        }
+        Ok(ImageInfo {
+            image_index,
+            width,
+            height,
+            color_type: color_type_name(colortype),
+            bit_depth: output_depth(colortype, options),
+            metadata: (!metadata.is_empty()).then_some(metadata),
+        })
+    })?;
+    Ok(ProbeResult::new(images, errors, None))
+}
+
+/// Call `f` for each page selected by `options.frames`, collecting results and errors.
+fn for_each_image<T>(
+    tiff_data: &[u8],
+    options: &DecodeOptions,
+    mut f: impl FnMut(&mut Decoder<Cursor<&[u8]>>, usize) -> Result<T>,
+) -> Result<(Vec<T>, Vec<ImageDecodeError>)> {
     let cursor = Cursor::new(tiff_data);
     let mut decoder = Decoder::new(cursor)?;
 
-    let mut images = Vec::new();
+    let mut results = Vec::new();
     let mut errors = Vec::new();
     let mut image_index = 0;
 
     loop {
-        match decode_single_image(&mut decoder, image_index) {
-            Ok(decoded) => {
-                images.push(decoded);
-            }
-            Err(e) => {
-                errors.push(ImageDecodeError {
-                    image_index,
-                    message: format!("{e}"),
-                });
+        if options.frames.contains(image_index) {
+            match f(&mut decoder, image_index) {
+                Ok(result) => {
+                    results.push(result);
+                }
+                Err(e) => {
+                    errors.push(ImageDecodeError {
+                        image_index,
+                        message: format!("{e}"),
+                    });
+                }
             }
         }
 
         image_index += 1;
 
-        if !decoder.more_images() {
+        if !decoder.more_images() || options.frames.end.is_some_and(|end| image_index >= end) {
             break;
         }
         match decoder.next_image() {
@@ -50,57 +245,105 @@ pub fn decode_tiff(tiff_data: &[u8]) -> Result<DecodeResult> {
         }
     }
 
-    Ok(DecodeResult {
-        images,
-        errors,
-        metadata: None,
-    })
+    Ok((results, errors))
 }
 
 fn decode_single_image(
     decoder: &mut Decoder<Cursor<&[u8]>>,
     image_index: usize,
+    options: &DecodeOptions,
 ) -> Result<DecodedImage> {
     let (width, height) = decoder.dimensions()?;
+    options.check_dimensions(width, height)?;
     let colortype = decoder.colortype()?;
+    let mut metadata = HashMap::new();
+    if let Some(pixel_size) = read_pixel_size(decoder) {
+        pixel_size.add_metadata(&mut metadata);
+    }
+    if options.metadata == MetadataLevel::Full
+        && let Ok(description) = decoder.get_tag_ascii_string(Tag::ImageDescription)
+    {
+        metadata.insert(
+            "description".to_string(),
+            MetadataValue::String(description),
+        );
+    }
+    let icc_profile = decoder.get_tag_u8_vec(Tag::IccProfile).ok();
     let image_data = decoder.read_image()?;
+    let samples = options
+        .decode_samples()
+        .then(|| tiff_samples(&image_data, width, height));
 
-    let (rgb_data, png_color_type) = match (image_data, colortype) {
-        (DecodingResult::U8(data), ColorType::Gray(1)) => (
+    let (pixel_data, png_color_type, depth) = match (image_data, colortype, &options.intensity) {
+        (DecodingResult::U8(data), ColorType::Gray(8), Some(mapping)) => (
+            map_gray(
+                data.iter().map(|&v| v as f32).collect(),
+                width,
+                height,
+                mapping,
+                &mut metadata,
+            ),
+            png::ColorType::Grayscale,
+            BitDepth::Eight,
+        ),
+        (DecodingResult::U16(data), ColorType::Gray(16), Some(mapping)) => (
+            map_gray(
+                data.iter().map(|&v| v as f32).collect(),
+                width,
+                height,
+                mapping,
+                &mut metadata,
+            ),
+            png::ColorType::Grayscale,
+            BitDepth::Eight,
+        ),
+        (DecodingResult::U16(data), ColorType::Gray(16), _) if options.preserve_depth => (
+            to_be_bytes(&data),
+            png::ColorType::Grayscale,
+            BitDepth::Sixteen,
+        ),
+        (DecodingResult::U16(data), ColorType::RGB(16), _) if options.preserve_depth => {
+            (to_be_bytes(&data), png::ColorType::Rgb, BitDepth::Sixteen)
+        }
+        (DecodingResult::U16(data), ColorType::RGBA(16), _) if options.preserve_depth => {
+            (to_be_bytes(&data), png::ColorType::Rgba, BitDepth::Sixteen)
+        }
+        (DecodingResult::U8(data), ColorType::Gray(1), _) => (
             data.iter().map(|&b| if b != 0 { 255 } else { 0 }).collect(),
             png::ColorType::Grayscale,
+            BitDepth::Eight,
         ),
-        (DecodingResult::U8(data), ColorType::Gray(8)) => {
-            // Convert grayscale to RGB
-            let mut rgb_data = Vec::with_capacity(data.len() * 3);
-            for gray in data {
-                rgb_data.extend_from_slice(&[gray, gray, gray]);
-            }
-            (rgb_data, png::ColorType::Rgb)
+        (DecodingResult::U8(data), ColorType::Gray(8), _) => {
+            (data, png::ColorType::Grayscale, BitDepth::Eight)
         }
-        (DecodingResult::U8(data), ColorType::RGB(8)) => (data, png::ColorType::Rgb),
-        (DecodingResult::U8(data), ColorType::RGBA(8)) => (data, png::ColorType::Rgba),
-        (DecodingResult::U16(data), ColorType::Gray(16)) => {
-            // Convert 16-bit grayscale to 8-bit RGB
-            let mut rgb_data = Vec::with_capacity(data.len() * 3);
-            for gray in data {
-                let gray_8 = convert_16_to_8(gray);
-                rgb_data.extend_from_slice(&[gray_8, gray_8, gray_8]);
-            }
-            (rgb_data, png::ColorType::Rgb)
+        (DecodingResult::U8(data), ColorType::RGB(8), _) => {
+            (data, png::ColorType::Rgb, BitDepth::Eight)
+        }
+        (DecodingResult::U8(data), ColorType::RGBA(8), _) => {
+            (data, png::ColorType::Rgba, BitDepth::Eight)
         }
-        (DecodingResult::U16(data), ColorType::RGB(16)) => {
+        (DecodingResult::U16(data), ColorType::Gray(16), _) => {
+            // Convert 16-bit grayscale to 8-bit
+            (
+                data.into_iter().map(convert_16_to_8).collect(),
+                png::ColorType::Grayscale,
+                BitDepth::Eight,
+            )
+        }
+        (DecodingResult::U16(data), ColorType::RGB(16), _) => {
             // Convert 16-bit RGB to 8-bit RGB
             (
                 data.iter().map(|&c| convert_16_to_8(c)).collect(),
                 png::ColorType::Rgb,
+                BitDepth::Eight,
             )
         }
-        (DecodingResult::U16(data), ColorType::RGBA(16)) => {
+        (DecodingResult::U16(data), ColorType::RGBA(16), _) => {
             // Convert 16-bit RGBA to 8-bit RGBA
             (
                 data.iter().map(|&c| convert_16_to_8(c)).collect(),
                 png::ColorType::Rgba,
+                BitDepth::Eight,
             )
         }
         _ => {
@@ -108,36 +351,72 @@ fn decode_single_image(
         }
     };
 
-    let (bit_depth, color_type_str) = match colortype {
-        ColorType::Gray(depth) => (depth, "Grayscale".to_string()),
-        ColorType::RGB(depth) => (depth, "RGB".to_string()),
-        ColorType::RGBA(depth) => (depth, "RGBA".to_string()),
-        ColorType::CMYK(depth) => (depth, "CMYK".to_string()),
-        ColorType::YCbCr(depth) => (depth, "YCbCr".to_string()),
-        ColorType::Palette(depth) => (depth, "Palette".to_string()),
-        ColorType::GrayA(depth) => (depth, "GrayscaleAlpha".to_string()),
-        ColorType::CMYKA(depth) => (depth, "CMYKA".to_string()),
-        ColorType::Multiband {
-            bit_depth,
-            num_samples,
-        } => (bit_depth, format!("Multiband{num_samples}")),
-        _ => (0, "Unknown".to_string()),
-    };
-
     let info = ImageInfo {
         image_index,
         width,
         height,
-        color_type: color_type_str,
-        bit_depth,
-        metadata: None,
+        color_type: color_type_name(colortype),
+        bit_depth: if depth == BitDepth::Sixteen { 16 } else { 8 },
+        metadata: (!metadata.is_empty()).then_some(metadata),
     };
 
     Ok(DecodedImage {
         width,
         height,
         color_type: png_color_type,
-        data: rgb_data,
+        depth,
+        data: pixel_data,
         info,
+        samples,
+        icc_profile,
     })
 }
+
+/// The decoded samples at their original precision (half floats are widened to 32 bits).
+fn tiff_samples(data: &DecodingResult, width: u32, height: u32) -> Samples {
+    match data {
+        DecodingResult::U8(values) => Samples::new(values, width, height),
+        DecodingResult::U16(values) => Samples::new(values, width, height),
+        DecodingResult::U32(values) => Samples::new(values, width, height),
+        DecodingResult::U64(values) => Samples::new(values, width, height),
+        DecodingResult::I8(values) => Samples::new(values, width, height),
+        DecodingResult::I16(values) => Samples::new(values, width, height),
+        DecodingResult::I32(values) => Samples::new(values, width, height),
+        DecodingResult::I64(values) => Samples::new(values, width, height),
+        DecodingResult::F16(values) => {
+            let values: Vec<f32> = values.iter().map(|value| value.to_f32()).collect();
+            Samples::new(&values, width, height)
+        }
+        DecodingResult::F32(values) => Samples::new(values, width, height),
+        DecodingResult::F64(values) => Samples::new(values, width, height),
+    }
+}
+
+/// A descriptive name for `colortype`.
+fn color_type_name(colortype: ColorType) -> String {
+    match colortype {
+        ColorType::Gray(_) => "Grayscale".to_string(),
+        ColorType::RGB(_) => "RGB".to_string(),
+        ColorType::RGBA(_) => "RGBA".to_string(),
+        ColorType::CMYK(_) => "CMYK".to_string(),
+        ColorType::YCbCr(_) => "YCbCr".to_string(),
+        ColorType::Palette(_) => "Palette".to_string(),
+        ColorType::GrayA(_) => "GrayscaleAlpha".to_string(),
+        ColorType::CMYKA(_) => "CMYKA".to_string(),
+        ColorType::Multiband { num_samples, .. } => format!("Multiband{num_samples}"),
+        _ => "Unknown".to_string(),
+    }
+}
+
+/// The bit depth pages of `colortype` are decoded to, as in [`decode_single_image`].
+fn output_depth(colortype: ColorType, options: &DecodeOptions) -> u8 {
+    match colortype {
+        ColorType::Gray(16) if options.intensity.is_some() => 8,
+        ColorType::Gray(16) | ColorType::RGB(16) | ColorType::RGBA(16)
+            if options.preserve_depth =>
+        {
+            16
+        }
+        _ => 8,
+    }
+}
@@ -0,0 +1,144 @@
+use crate::metadata::MetadataValue;
+use crate::options::{TiffCompression, TiffOptions};
+use crate::pixel_size::PixelSize;
+use crate::png::icc_matches;
+use crate::raw::to_rgba8;
+use crate::typ::{DecodedImage, SampleType};
+use anyhow::Result;
+use png::{BitDepth, ColorType};
+use std::io::{Cursor, Seek, Write};
+use tiff::encoder::colortype::{self, ColorType as TiffColorType};
+use tiff::encoder::compression::DeflateLevel;
+use tiff::encoder::{Compression, Rational, TiffEncoder, TiffValue};
+use tiff::tags::{ResolutionUnit, Tag};
+
+/// Encode `images` as the pages of a TIFF. Pages are written from the full-precision
+/// samples where available (8 to 64-bit integers and floats), or from the display data
+/// otherwise, along with the pixel size, description and ICC profile.
+pub fn encode_tiff(images: &[DecodedImage], options: &TiffOptions) -> Result<Vec<u8>> {
+    if images.is_empty() {
+        anyhow::bail!("No images to write");
+    }
+    let mut tiff_data = Vec::new();
+    {
+        let mut encoder = TiffEncoder::new(Cursor::new(&mut tiff_data))?.with_compression(
+            match options.compression {
+                TiffCompression::None => Compression::Uncompressed,
+                TiffCompression::Lzw => Compression::Lzw,
+                TiffCompression::Deflate => Compression::Deflate(DeflateLevel::Balanced),
+            },
+        );
+        for image in images {
+            write_image(&mut encoder, image)?;
+        }
+    }
+    Ok(tiff_data)
+}
+
+/// Write `image` as a single page, choosing the TIFF color type from its samples or pixel format.
+fn write_image<W: Write + Seek>(encoder: &mut TiffEncoder<W>, image: &DecodedImage) -> Result<()> {
+    macro_rules! page {
+        ($color:ident, $values:expr, $color_type:ident) => {
+            write_page::<W, colortype::$color>(encoder, image, &$values, ColorType::$color_type)
+        };
+    }
+
+    if let Some(samples) = &image.samples {
+        macro_rules! samples {
+            ($color:ident, $t:ty, $color_type:ident) => {
+                page!($color, samples.values::<$t>(), $color_type)
+            };
+        }
+        match (samples.dtype, samples.shape[2]) {
+            (SampleType::Uint8, 1) => return samples!(Gray8, u8, Grayscale),
+            (SampleType::Uint8, 3) => return samples!(RGB8, u8, Rgb),
+            (SampleType::Uint8, 4) => return samples!(RGBA8, u8, Rgba),
+            (SampleType::Int8, 1) => return samples!(GrayI8, i8, Grayscale),
+            (SampleType::Uint16, 1) => return samples!(Gray16, u16, Grayscale),
+            (SampleType::Uint16, 3) => return samples!(RGB16, u16, Rgb),
+            (SampleType::Uint16, 4) => return samples!(RGBA16, u16, Rgba),
+            (SampleType::Int16, 1) => return samples!(GrayI16, i16, Grayscale),
+            (SampleType::Uint32, 1) => return samples!(Gray32, u32, Grayscale),
+            (SampleType::Uint32, 3) => return samples!(RGB32, u32, Rgb),
+            (SampleType::Uint32, 4) => return samples!(RGBA32, u32, Rgba),
+            (SampleType::Int32, 1) => return samples!(GrayI32, i32, Grayscale),
+            (SampleType::Uint64, 1) => return samples!(Gray64, u64, Grayscale),
+            (SampleType::Int64, 1) => return samples!(GrayI64, i64, Grayscale),
+            (SampleType::Float32, 1) => return samples!(Gray32Float, f32, Grayscale),
+            (SampleType::Float32, 3) => return samples!(RGB32Float, f32, Rgb),
+            (SampleType::Float32, 4) => return samples!(RGBA32Float, f32, Rgba),
+            (SampleType::Float64, 1) => return samples!(Gray64Float, f64, Grayscale),
+            (SampleType::Float64, 3) => return samples!(RGB64Float, f64, Rgb),
+            (SampleType::Float64, 4) => return samples!(RGBA64Float, f64, Rgba),
+            // Complex values and other layouts TIFF can't store are written as displayed.
+            _ => {}
+        }
+    }
+
+    let wide = || -> Vec<u16> {
+        image
+            .data
+            .chunks_exact(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .collect()
+    };
+    match (image.color_type, image.depth) {
+        (ColorType::Grayscale, BitDepth::Eight) => page!(Gray8, image.data, Grayscale),
+        (ColorType::Grayscale, BitDepth::Sixteen) => page!(Gray16, wide(), Grayscale),
+        (ColorType::Rgb, BitDepth::Eight) => page!(RGB8, image.data, Rgb),
+        (ColorType::Rgb, BitDepth::Sixteen) => page!(RGB16, wide(), Rgb),
+        (ColorType::Rgba, BitDepth::Eight) => page!(RGBA8, image.data, Rgba),
+        (ColorType::Rgba, BitDepth::Sixteen) => page!(RGBA16, wide(), Rgba),
+        // The encoder has no gray + alpha color type.
+        (ColorType::GrayscaleAlpha, _) => page!(RGBA8, to_rgba8(image)?, Rgba),
+        (color_type, depth) => {
+            anyhow::bail!("Can't write {color_type:?} images with {depth:?} depth as TIFF")
+        }
+    }
+}
+
+fn write_page<W: Write + Seek, C: TiffColorType>(
+    encoder: &mut TiffEncoder<W>,
+    image: &DecodedImage,
+    data: &[C::Inner],
+    color_type: ColorType,
+) -> Result<()>
+where
+    [C::Inner]: TiffValue,
+{
+    let mut page = encoder.new_image::<C>(image.width, image.height)?;
+    let metadata = image.info.metadata.as_ref();
+    if let Some(size) = metadata.and_then(PixelSize::from_metadata) {
+        let per_cm = |length: f64| pixels_per_cm(length * size.unit.nanometers());
+        if let (Some(x), Some(y)) = (per_cm(size.x), per_cm(size.y)) {
+            page.resolution_unit(ResolutionUnit::Centimeter);
+            page.x_resolution(x);
+            page.y_resolution(y);
+        }
+    }
+    let directory = page.encoder();
+    directory.write_tag(Tag::Software, "obscura-image")?;
+    if let Some(MetadataValue::String(description)) =
+        metadata.and_then(|metadata| metadata.get("description"))
+    {
+        directory.write_tag(Tag::ImageDescription, description.as_str())?;
+    }
+    if let Some(profile) = image
+        .icc_profile
+        .as_deref()
+        .filter(|profile| icc_matches(profile, color_type))
+    {
+        directory.write_tag(Tag::IccProfile, profile)?;
+    }
+    page.write_data(data)?;
+    Ok(())
+}
+
+/// Pixels per centimeter for a pixel `nanometers` wide, as precisely as a `Rational` allows.
+fn pixels_per_cm(nanometers: f64) -> Option<Rational> {
+    let per_cm = 1e7 / nanometers;
+    [1_000_000, 1_000, 1].into_iter().find_map(|d| {
+        let n = (per_cm * d as f64).round();
+        (n >= 1.0 && n <= u32::MAX as f64).then_some(Rational { n: n as u32, d })
+    })
+}
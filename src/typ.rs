@@ -1,20 +1,155 @@
 use crate::metadata::MetadataMap;
+use crate::options::OutputFormat;
 use serde::Serialize;
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct ImageInfo {
     pub image_index: usize,
     pub width: u32,
     pub height: u32,
+    /// The color type (TIFF) or mode (MRC) of the source image.
     pub color_type: String,
+    /// Bits per sample of the decoded image: 16 for 16-bit data kept with
+    /// `preserve_depth`, 8 otherwise.
     pub bit_depth: u8,
     pub metadata: Option<MetadataMap>,
 }
 
 #[derive(Serialize)]
 pub struct Image {
+    /// The encoded image for [`OutputFormat::Png`] and [`OutputFormat::Apng`]; empty otherwise.
     #[serde(with = "serde_bytes")]
     pub png_data: Vec<u8>,
+    /// The encoded image for the other output formats, or raw pixels for
+    /// [`OutputFormat::Rgba`]; empty for PNG output.
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    pub format: OutputFormat,
     pub info: ImageInfo,
+    pub samples: Option<Samples>,
+}
+
+impl Image {
+    /// Wrap the output `data` of `format`, as `png_data` for PNG output and as `data` otherwise.
+    pub fn new(
+        data: Vec<u8>,
+        format: OutputFormat,
+        info: ImageInfo,
+        samples: Option<Samples>,
+    ) -> Self {
+        let (png_data, data) = match format {
+            OutputFormat::Png | OutputFormat::Apng => (data, Vec::new()),
+            _ => (Vec::new(), data),
+        };
+        Image {
+            png_data,
+            data,
+            format,
+            info,
+            samples,
+        }
+    }
+
+    /// The output data, whichever field it is in.
+    pub fn output_data(&self) -> &[u8] {
+        match self.format {
+            OutputFormat::Png | OutputFormat::Apng => &self.png_data,
+            _ => &self.data,
+        }
+    }
+}
+
+/// Element type of [`Samples`], named after the JavaScript typed array it maps to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleType {
+    Uint8,
+    Int8,
+    Uint16,
+    Int16,
+    Uint32,
+    Int32,
+    Uint64,
+    Int64,
+    Float32,
+    Float64,
+}
+
+/// Pixel values at their original precision.
+#[derive(Clone, Debug, Serialize)]
+pub struct Samples {
+    /// Little-endian values, row by row, with the channels of each pixel interleaved.
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    pub dtype: SampleType,
+    /// `[height, width, channels]`
+    pub shape: [usize; 3],
+}
+
+/// Types that can be stored in [`Samples`].
+pub trait SampleValue: bytemuck::Pod {
+    const DTYPE: SampleType;
+}
+
+macro_rules! impl_sample_value {
+    ($($t:ty => $dtype:ident),* $(,)?) => {
+        $(
+            impl SampleValue for $t {
+                const DTYPE: SampleType = SampleType::$dtype;
+            }
+        )*
+    };
+}
+
+impl_sample_value!(
+    u8 => Uint8,
+    i8 => Int8,
+    u16 => Uint16,
+    i16 => Int16,
+    u32 => Uint32,
+    i32 => Int32,
+    u64 => Uint64,
+    i64 => Int64,
+    f32 => Float32,
+    f64 => Float64,
+);
+
+impl Samples {
+    /// Wrap interleaved `values`; the channel count follows from their number.
+    pub fn new<T: SampleValue>(values: &[T], width: u32, height: u32) -> Self {
+        let channels = values.len() / (width as usize * height as usize).max(1);
+        Samples {
+            data: bytemuck::cast_slice(values).to_vec(),
+            dtype: T::DTYPE,
+            shape: [height as usize, width as usize, channels],
+        }
+    }
+
+    /// The values as `T`, which should match `dtype`.
+    pub fn values<T: SampleValue>(&self) -> Vec<T> {
+        self.data
+            .chunks_exact(size_of::<T>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect()
+    }
+
+    /// The values converted to `f64`, e.g. for statistics.
+    pub fn to_f64(&self) -> Vec<f64> {
+        fn convert<T: SampleValue + Into<f64>>(samples: &Samples) -> Vec<f64> {
+            samples.values::<T>().into_iter().map(Into::into).collect()
+        }
+        match self.dtype {
+            SampleType::Uint8 => convert::<u8>(self),
+            SampleType::Int8 => convert::<i8>(self),
+            SampleType::Uint16 => convert::<u16>(self),
+            SampleType::Int16 => convert::<i16>(self),
+            SampleType::Uint32 => convert::<u32>(self),
+            SampleType::Int32 => convert::<i32>(self),
+            SampleType::Uint64 => self.values::<u64>().into_iter().map(|v| v as f64).collect(),
+            SampleType::Int64 => self.values::<i64>().into_iter().map(|v| v as f64).collect(),
+            SampleType::Float32 => convert::<f32>(self),
+            SampleType::Float64 => convert::<f64>(self),
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -29,14 +164,56 @@ pub struct Output {
     pub errors: Vec<ImageDecodeError>,
     pub total_images: usize,
     pub metadata: Option<MetadataMap>,
+    /// The output format and encoder settings used for the images.
+    pub encoding: MetadataMap,
+}
+
+/// Image information without pixel data, as returned by the probe functions.
+#[derive(Serialize)]
+pub struct ProbeResult {
+    pub images: Vec<ImageInfo>,
+    pub errors: Vec<ImageDecodeError>,
+    pub total_images: usize,
+    pub metadata: Option<MetadataMap>,
+}
+
+impl ProbeResult {
+    pub fn new(
+        images: Vec<ImageInfo>,
+        errors: Vec<ImageDecodeError>,
+        metadata: Option<MetadataMap>,
+    ) -> Self {
+        let total_images = images.len() + errors.len();
+        ProbeResult {
+            images,
+            errors,
+            total_images,
+            metadata,
+        }
+    }
+
+    /// Drop the file metadata and the metadata of every image.
+    pub fn strip_metadata(&mut self) {
+        self.metadata = None;
+        for image in &mut self.images {
+            image.metadata = None;
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct DecodedImage {
     pub width: u32,
     pub height: u32,
     pub color_type: png::ColorType,
+    /// Eight or sixteen bits per sample; 16-bit samples are stored big-endian, as in PNG.
+    pub depth: png::BitDepth,
     pub data: Vec<u8>,
     pub info: ImageInfo,
+    /// The original pixel values, if `samples` was requested in the options.
+    pub samples: Option<Samples>,
+    /// ICC color profile of the source image.
+    pub icc_profile: Option<Vec<u8>>,
 }
 
 pub struct DecodeResult {
@@ -44,3 +221,13 @@ pub struct DecodeResult {
     pub errors: Vec<ImageDecodeError>,
     pub metadata: Option<MetadataMap>,
 }
+
+impl DecodeResult {
+    /// Drop the file metadata and the metadata of every image.
+    pub fn strip_metadata(&mut self) {
+        self.metadata = None;
+        for image in &mut self.images {
+            image.info.metadata = None;
+        }
+    }
+}
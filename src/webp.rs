@@ -0,0 +1,23 @@
+use crate::raw::to_8bit;
+use crate::typ::DecodedImage;
+use anyhow::Result;
+use image_webp::{ColorType as WebpColorType, WebPEncoder};
+use png::ColorType;
+
+/// Encode `image` as lossless WebP (VP8L). 16-bit data is reduced to 8 bits,
+/// as WebP has no higher bit depths.
+pub fn encode_webp(image: &DecodedImage) -> Result<Vec<u8>> {
+    let color = match image.color_type {
+        ColorType::Grayscale => WebpColorType::L8,
+        ColorType::GrayscaleAlpha => WebpColorType::La8,
+        ColorType::Rgb => WebpColorType::Rgb8,
+        ColorType::Rgba => WebpColorType::Rgba8,
+        ColorType::Indexed => anyhow::bail!("Indexed images can't be encoded as WebP"),
+    };
+    let data = to_8bit(image)?;
+    let mut webp_data = Vec::new();
+    WebPEncoder::new(&mut webp_data)
+        .encode(&data, image.width, image.height, color)
+        .map_err(|e| anyhow::anyhow!("Failed to encode WebP: {e}"))?;
+    Ok(webp_data)
+}
@@ -1,4 +1,4 @@
-import init, { decodeMrc, decodeTiff } from "../pkg/obscura_image.js";
+import init, { decode } from "../pkg/obscura_image.js";
 
 function dumpMetadata(metadata) {
   let html = "<table>";
@@ -26,9 +26,7 @@ async function wrapProcess(processor) {
 async function processImageData(filename, arrayBuffer) {
   const fileData = new Uint8Array(arrayBuffer);
   console.log(`Input file: ${filename}, size: ${fileData.length} bytes`);
-  const result = filename.toLowerCase().endsWith(".mrc")
-    ? decodeMrc(fileData)
-    : decodeTiff(fileData);
+  const result = decode(fileData);
   console.log("Decoding result:", result);
   let html = `
 <div class="success">
@@ -0,0 +1,60 @@
+#![allow(dead_code)]
+
+use obscura_image::options::{DecodeOptions, OutputFormat};
+use obscura_image::typ::{Image, Output};
+use obscura_image::{decode_any, encode_result_with_options};
+
+/// Options for `output`, with everything else at the defaults.
+pub fn output_options(output: OutputFormat) -> DecodeOptions {
+    DecodeOptions {
+        output,
+        ..Default::default()
+    }
+}
+
+/// Decode `data` and encode the result with `options`.
+pub fn encode(data: &[u8], options: &DecodeOptions) -> anyhow::Result<Output> {
+    encode_result_with_options(decode_any(data, options)?, options)
+}
+
+/// Decode `data` and encode its first image with `options`.
+pub fn encode_first(data: &[u8], options: &DecodeOptions) -> Image {
+    encode(data, options).unwrap().images.remove(0)
+}
+
+/// Build a minimal little-endian MRC 2014 file around raw voxel `data`.
+pub fn make_mrc(nx: i32, ny: i32, nz: i32, mode: i32, data: &[u8]) -> Vec<u8> {
+    let mut header = vec![0u8; 1024];
+    let mut put_i32 = |offset: usize, value: i32| {
+        header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    };
+    put_i32(0, nx);
+    put_i32(4, ny);
+    put_i32(8, nz);
+    put_i32(12, mode);
+    put_i32(28, nx);
+    put_i32(32, ny);
+    put_i32(36, nz);
+    put_i32(64, 1);
+    put_i32(68, 2);
+    put_i32(72, 3);
+    put_i32(96 + 12, 20140);
+    header[40..44].copy_from_slice(&(nx as f32).to_le_bytes());
+    header[44..48].copy_from_slice(&(ny as f32).to_le_bytes());
+    header[48..52].copy_from_slice(&(nz as f32).to_le_bytes());
+    for offset in [52, 56, 60] {
+        header[offset..offset + 4].copy_from_slice(&90f32.to_le_bytes());
+    }
+    header[208..212].copy_from_slice(b"MAP ");
+    header[212..216].copy_from_slice(&[0x44, 0x44, 0, 0]);
+    header.extend_from_slice(data);
+    header
+}
+
+pub fn f32_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+pub fn i16_bytes(values: &[i16]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{encode, f32_bytes, make_mrc, output_options};
+use obscura_image::options::{Colormap, DecodeOptions, NamedColormap, OutputFormat};
+use obscura_image::typ::Output;
+use std::fs;
+
+fn colored(data: &[u8], colormap: Colormap) -> anyhow::Result<Output> {
+    let options = DecodeOptions {
+        colormap: Some(colormap),
+        ..output_options(OutputFormat::Rgba)
+    };
+    encode(data, &options)
+}
+
+/// A 4x1 MRC that maps to 8-bit values 0, 85, 170 and 255.
+fn ramp() -> Vec<u8> {
+    make_mrc(4, 1, 1, 2, &f32_bytes(&[0.0, 1.0, 2.0, 3.0]))
+}
+
+fn pixels(output: &Output) -> Vec<[u8; 4]> {
+    output.images[0]
+        .data
+        .chunks_exact(4)
+        .map(|pixel| pixel.try_into().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_named_colormap() {
+    let output = colored(&ramp(), Colormap::Named(NamedColormap::Viridis)).unwrap();
+    let colors = pixels(&output);
+    assert_eq!(colors[0], [0x44, 0x01, 0x54, 255]);
+    assert_eq!(colors[3], [0xfd, 0xe7, 0x25, 255]);
+    assert_eq!(format!("{}", output.encoding["colormap"]), "viridis");
+    let meta = output.images[0].info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["colormap"]), "viridis");
+
+    let output = colored(&ramp(), Colormap::Named(NamedColormap::RdBu)).unwrap();
+    let colors = pixels(&output);
+    assert_eq!(colors[0], [0x67, 0x00, 0x1f, 255]);
+    assert_eq!(colors[3], [0x05, 0x30, 0x61, 255]);
+}
+
+#[test]
+fn test_custom_colormap() {
+    let output = colored(&ramp(), Colormap::Custom(vec![[0, 0, 0], [255, 0, 0]])).unwrap();
+    let red: Vec<u8> = pixels(&output).iter().map(|pixel| pixel[0]).collect();
+    assert_eq!(red, [0, 85, 170, 255]);
+    assert!(pixels(&output).iter().all(|pixel| pixel[1] == 0));
+
+    assert!(colored(&ramp(), Colormap::Custom(vec![[0, 0, 0]])).is_err());
+}
+
+#[test]
+fn test_colormap_tiff() {
+    // Grayscale TIFFs are colored, color ones are left as they are.
+    let gray = fs::read("tests/gray8.tiff").unwrap();
+    let output = colored(&gray, Colormap::Named(NamedColormap::Magma)).unwrap();
+    assert!(pixels(&output).iter().any(|pixel| pixel[0] != pixel[2]));
+
+    let rgb = fs::read("tests/rgb8.tiff").unwrap();
+    let plain = encode(&rgb, &output_options(OutputFormat::Rgba)).unwrap();
+    let colored = colored(&rgb, Colormap::Named(NamedColormap::Hot)).unwrap();
+    assert_eq!(pixels(&plain), pixels(&colored));
+}
+
+#[test]
+fn test_colormap_refuses_sample_output() {
+    let gray = fs::read("tests/gray8.tiff").unwrap();
+    for output in [OutputFormat::Tiff, OutputFormat::Mrc] {
+        let options = DecodeOptions {
+            colormap: Some(Colormap::Named(NamedColormap::Viridis)),
+            ..output_options(output)
+        };
+        let Err(error) = encode(&gray, &options) else {
+            panic!("Expected a colormap with {output:?} output to be refused");
+        };
+        assert!(format!("{error}").contains("can't be colored"));
+    }
+}
+
+#[test]
+fn test_colormap_options() {
+    let parse = |json: &str| serde_json::from_str::<DecodeOptions>(json).map(|o| o.colormap);
+    assert_eq!(
+        parse(r#"{"colormap": "cividis"}"#).unwrap(),
+        Some(Colormap::Named(NamedColormap::Cividis))
+    );
+    assert_eq!(
+        parse(r#"{"colormap": [[0, 0, 0], [255, 255, 255]]}"#).unwrap(),
+        Some(Colormap::Custom(vec![[0, 0, 0], [255, 255, 255]]))
+    );
+    assert!(parse(r#"{"colormap": "jet"}"#).is_err());
+}
@@ -0,0 +1,94 @@
+use obscura_image::compression::{Compression, decode_wrapped};
+use obscura_image::mrc::decode_mrc_with_options;
+use obscura_image::options::DecodeOptions;
+use obscura_image::tiff::decode_tiff_with_options;
+use std::fs;
+use std::io::Write;
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn bzip2(data: &[u8]) -> Vec<u8> {
+    let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn zstd(data: &[u8]) -> Vec<u8> {
+    ruzstd::encoding::compress_to_vec(data, ruzstd::encoding::CompressionLevel::Fastest)
+}
+
+#[test]
+fn test_compressed_mrc() {
+    let mrc_data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let expected_slices = decode_mrc_with_options(&mrc_data, &DecodeOptions::default())
+        .unwrap()
+        .images
+        .len();
+    for (compress, name) in [
+        (gzip as fn(&[u8]) -> Vec<u8>, "gzip"),
+        (bzip2, "bzip2"),
+        (zstd, "zstd"),
+    ] {
+        let compressed = compress(&mrc_data);
+        let result = decode_wrapped(
+            &compressed,
+            &DecodeOptions::default(),
+            decode_mrc_with_options,
+        )
+        .unwrap();
+        assert_eq!(result.images.len(), expected_slices);
+        let meta = result.metadata.as_ref().unwrap();
+        assert_eq!(format!("{}", meta["compression"]), name);
+        assert_eq!(
+            format!("{}", meta["decompressed_size"]),
+            mrc_data.len().to_string()
+        );
+    }
+}
+
+#[test]
+fn test_compressed_tiff() {
+    let tiff_data = fs::read("tests/multipage.tiff").unwrap();
+    let result = decode_wrapped(
+        &gzip(&tiff_data),
+        &DecodeOptions::default(),
+        decode_tiff_with_options,
+    )
+    .unwrap();
+    assert_eq!(result.images.len(), 2);
+    let meta = result.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["compression"]), "gzip");
+}
+
+#[test]
+fn test_uncompressed_passthrough() {
+    let tiff_data = fs::read("tests/rgb8.tiff").unwrap();
+    assert_eq!(Compression::detect(&tiff_data), None);
+    let result = decode_wrapped(
+        &tiff_data,
+        &DecodeOptions::default(),
+        decode_tiff_with_options,
+    )
+    .unwrap();
+    assert!(result.metadata.is_none());
+}
+
+#[test]
+fn test_decompressed_size_cap() {
+    let mrc_data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let options = DecodeOptions {
+        max_decompressed_size: 4096,
+        ..Default::default()
+    };
+    let err = decode_wrapped(&zstd(&mrc_data), &options, decode_mrc_with_options)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Decompressed zstd data exceeds the limit of 4096 bytes"
+    );
+}
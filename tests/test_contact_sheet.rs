@@ -0,0 +1,143 @@
+mod common;
+
+use common::{encode, output_options};
+use obscura_image::options::{ContactSheetOptions, DecodeOptions, OutputFormat};
+use obscura_image::typ::Image;
+use std::fs;
+
+fn contact_sheet(path: &str, sheet: ContactSheetOptions) -> Image {
+    let options = DecodeOptions {
+        contact_sheet: Some(sheet),
+        ..output_options(OutputFormat::Rgba)
+    };
+    let output = encode(&fs::read(path).unwrap(), &options).unwrap();
+    assert_eq!(output.images.len(), 1);
+    output.images.into_iter().next().unwrap()
+}
+
+fn pixel(image: &Image, x: u32, y: u32) -> &[u8] {
+    let offset = ((y * image.info.width + x) * 4) as usize;
+    &image.data[offset..offset + 4]
+}
+
+fn metadata(image: &Image, key: &str) -> String {
+    format!("{}", image.info.metadata.as_ref().unwrap()[key])
+}
+
+#[test]
+fn test_contact_sheet_grid() {
+    let image = contact_sheet(
+        "tests/EMD-3197.mrc",
+        ContactSheetOptions {
+            tile_size: 16,
+            spacing: 2,
+            ..Default::default()
+        },
+    );
+    // 20 slices in a 5x4 grid
+    assert_eq!(
+        (image.info.width, image.info.height),
+        (5 * 16 + 6 * 2, 4 * 16 + 5 * 2)
+    );
+    assert_eq!(metadata(&image, "montage_columns"), "5");
+    assert_eq!(metadata(&image, "montage_rows"), "4");
+    assert_eq!(metadata(&image, "tile_count"), "20");
+
+    let tile: Vec<u32> = metadata(&image, "tile_19")
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect();
+    let [x, y, width, height] = tile[..] else {
+        panic!("Expected x,y,width,height");
+    };
+    // The 20x20 slices are shrunk to fill the cell.
+    assert_eq!((width, height), (16, 16));
+    assert_eq!((x, y), (4 * 18 + 2, 3 * 18 + 2));
+    assert!(x + width <= image.info.width && y + height <= image.info.height);
+    // The spacing is left as background.
+    assert_eq!(pixel(&image, 0, 0), pixel(&image, 1, image.info.height - 1));
+}
+
+#[test]
+fn test_contact_sheet_labels() {
+    let sheet = ContactSheetOptions {
+        columns: Some(1),
+        tile_size: 64,
+        spacing: 4,
+        labels: true,
+        ..Default::default()
+    };
+    let image = contact_sheet("tests/multipage.tiff", sheet);
+    assert_eq!((image.info.width, image.info.height), (72, 2 * 64 + 3 * 4));
+    // "0" in the corner of the first cell, "1" in the second, on black.
+    assert_eq!(pixel(&image, 4, 4), [0, 0, 0, 255]);
+    assert_eq!(pixel(&image, 6, 6), [255, 255, 255, 255]);
+    assert_eq!(pixel(&image, 6, 74), [0, 0, 0, 255]);
+    assert_eq!(pixel(&image, 8, 74), [255, 255, 255, 255]);
+}
+
+#[test]
+fn test_labels_stay_in_their_cells() {
+    // Two-digit labels are wider than the 8 pixel tiles.
+    let sheet = |labels| ContactSheetOptions {
+        columns: Some(5),
+        tile_size: 8,
+        spacing: 2,
+        labels,
+        ..Default::default()
+    };
+    let plain = contact_sheet("tests/EMD-3197.mrc", sheet(false));
+    let labeled = contact_sheet("tests/EMD-3197.mrc", sheet(true));
+    let in_cell = |position: u32| position % 10 >= 2;
+    let mut changed = 0;
+    for y in 0..labeled.info.height {
+        for x in 0..labeled.info.width {
+            if pixel(&plain, x, y) != pixel(&labeled, x, y) {
+                assert!(
+                    in_cell(x) && in_cell(y),
+                    "label drawn outside its cell at {x},{y}"
+                );
+                changed += 1;
+            }
+        }
+    }
+    assert!(changed > 0);
+    // The label of cell 10 covers its cell up to the edge, not the spacing after it.
+    assert_eq!(pixel(&labeled, 2 + 7, 2 * 10 + 2), [0, 0, 0, 255]);
+}
+
+#[test]
+fn test_contact_sheet_size_limits() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let sheet = |tile_size| DecodeOptions {
+        contact_sheet: Some(ContactSheetOptions {
+            tile_size,
+            ..Default::default()
+        }),
+        max_width: Some(8192),
+        max_height: Some(8192),
+        ..output_options(OutputFormat::Rgba)
+    };
+    // 20 slices in a 5x4 grid
+    let Err(error) = encode(&data, &sheet(2048)) else {
+        panic!("Expected a sheet beyond the size limits to be refused");
+    };
+    assert!(format!("{error}").contains("exceeds the maximum"));
+    let Err(error) = encode(&data, &sheet(u32::MAX)) else {
+        panic!("Expected an overflowing sheet to be refused");
+    };
+    assert!(format!("{error}").contains("overflows"));
+}
+
+#[test]
+fn test_contact_sheet_refuses_mrc() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let options = DecodeOptions {
+        contact_sheet: Some(ContactSheetOptions::default()),
+        ..output_options(OutputFormat::Mrc)
+    };
+    let Err(error) = encode(&data, &options) else {
+        panic!("Expected a contact sheet written as MRC to be refused");
+    };
+    assert!(format!("{error}").contains("contact sheet"));
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{f32_bytes, make_mrc};
+use obscura_image::image_file::ImageFile;
+use obscura_image::mrc::StackMode;
+use obscura_image::options::{DecodeOptions, FrameSelection};
+use std::fs;
+
+#[test]
+fn test_tiff_pages() {
+    let data = fs::read("tests/multipage.tiff").unwrap();
+    let file = ImageFile::open(&data, None, DecodeOptions::default()).unwrap();
+    assert_eq!(file.format().name(), "tiff");
+    assert_eq!(file.page_count(), 2);
+    assert_eq!(file.info().images.len(), 2);
+
+    let page = file.decode_page(1).unwrap();
+    assert_eq!(page.info.image_index, 1);
+    assert_eq!((page.width, page.height), (64, 64));
+    assert!(file.decode_page(2).is_err());
+}
+
+#[test]
+fn test_tiff_pages_ignore_stack_mode() {
+    // The stack mode only applies to MRC files; TIFF pages are still decoded one by one.
+    let data = fs::read("tests/multipage.tiff").unwrap();
+    let options = DecodeOptions {
+        stack: StackMode::Average,
+        ..Default::default()
+    };
+    let file = ImageFile::open(&data, None, options).unwrap();
+    assert_eq!(file.page_count(), 2);
+    assert_eq!(file.decode_page(1).unwrap().info.image_index, 1);
+}
+
+#[test]
+fn test_mrc_slices() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let file = ImageFile::open(&data, None, DecodeOptions::default()).unwrap();
+    assert_eq!(file.page_count(), 20);
+    let page = file.decode_page(5).unwrap();
+    assert_eq!(page.info.image_index, 5);
+    assert_eq!(
+        (page.width, page.height),
+        (file.info().images[5].width, file.info().images[5].height)
+    );
+}
+
+#[test]
+fn test_selected_frames() {
+    let values: Vec<f32> = (0..64).map(|v| v as f32).collect();
+    let data = make_mrc(4, 4, 4, 2, &f32_bytes(&values));
+    let options = DecodeOptions {
+        frames: FrameSelection {
+            start: 1,
+            end: None,
+            step: 2,
+        },
+        ..Default::default()
+    };
+    let file = ImageFile::open(&data, None, options).unwrap();
+    assert_eq!(file.page_count(), 2);
+    assert_eq!(file.decode_page(1).unwrap().info.image_index, 3);
+}
+
+#[test]
+fn test_composite_page() {
+    let data = make_mrc(3, 2, 5, 2, &f32_bytes(&[1.0; 30]));
+    let options = DecodeOptions {
+        stack: StackMode::Montage { columns: None },
+        ..Default::default()
+    };
+    let file = ImageFile::open(&data, None, options).unwrap();
+    assert_eq!(file.page_count(), 1);
+    let page = file.decode_page(0).unwrap();
+    assert_eq!((page.width, page.height), (9, 4));
+    // Decoded once and handed out again.
+    let again = file.decode_page(0).unwrap();
+    assert_eq!((again.data, again.info.width), (page.data, page.info.width));
+}
+
+#[test]
+fn test_truncated_page() {
+    let mut data = make_mrc(4, 4, 3, 2, &f32_bytes(&[0.0; 48]));
+    data.truncate(data.len() - 4);
+    let file = ImageFile::open(&data, None, DecodeOptions::default()).unwrap();
+    assert_eq!(file.page_count(), 3);
+    assert!(file.decode_page(0).is_ok());
+    let error = file.decode_page(2).err().unwrap();
+    assert!(format!("{error}").contains("slice 2"));
+}
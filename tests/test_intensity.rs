@@ -0,0 +1,80 @@
+mod common;
+
+use common::{f32_bytes, make_mrc};
+use obscura_image::intensity::IntensityMapping;
+use obscura_image::mrc::decode_mrc_with_options;
+use obscura_image::options::DecodeOptions;
+use obscura_image::tiff::decode_tiff_with_options;
+use std::collections::HashSet;
+use std::fs;
+
+/// A 16x16 ramp with a single hot pixel that dwarfs everything else.
+fn hot_pixel_mrc() -> Vec<u8> {
+    let mut values: Vec<f32> = (0..256).map(|v| v as f32).collect();
+    values[17] = 1.0e6;
+    make_mrc(16, 16, 1, 2, &f32_bytes(&values))
+}
+
+fn decode_with(mapping: Option<IntensityMapping>) -> Vec<u8> {
+    let options = DecodeOptions {
+        intensity: mapping,
+        ..Default::default()
+    };
+    let result = decode_mrc_with_options(&hot_pixel_mrc(), &options).unwrap();
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    let image = result.images.into_iter().next().unwrap();
+    let meta = image.info.metadata.as_ref().unwrap();
+    let expected = mapping.unwrap_or(IntensityMapping::MinMax).name();
+    assert_eq!(format!("{}", meta["intensity_mapping"]), expected);
+    image.data
+}
+
+fn distinct(data: &[u8]) -> usize {
+    data.iter().collect::<HashSet<_>>().len()
+}
+
+#[test]
+fn test_min_max_is_flattened_by_hot_pixel() {
+    let data = decode_with(None);
+    assert_eq!(distinct(&data), 2);
+}
+
+#[test]
+fn test_percentile_clipping() {
+    let data = decode_with(Some(IntensityMapping::Percentile {
+        low: 0.5,
+        high: 99.5,
+    }));
+    assert!(distinct(&data) > 200);
+    assert_eq!(data[0], 0);
+    assert_eq!(data[17], 255);
+}
+
+#[test]
+fn test_equalize() {
+    let data = decode_with(Some(IntensityMapping::Equalize));
+    assert!(distinct(&data) > 200);
+    assert_eq!(data.iter().max(), Some(&255));
+}
+
+#[test]
+fn test_clahe() {
+    let data = decode_with(Some(IntensityMapping::Clahe {
+        tiles: 4,
+        clip_limit: 2.0,
+    }));
+    assert_eq!(data.len(), 256);
+    assert!(distinct(&data) > 16);
+}
+
+#[test]
+fn test_tiff_gray_mapping() {
+    let options = DecodeOptions {
+        intensity: Some(IntensityMapping::Equalize),
+        ..Default::default()
+    };
+    let tiff_data = fs::read("tests/gray8.tiff").unwrap();
+    let result = decode_tiff_with_options(&tiff_data, &options).unwrap();
+    let meta = result.images[0].info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["intensity_mapping"]), "equalize");
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::{encode, output_options};
+use obscura_image::options::{DecodeOptions, JpegOptions, OutputFormat};
+use std::fs;
+use zune_jpeg::zune_core::colorspace::ColorSpace;
+
+fn encode_jpeg(path: &str, quality: u8) -> anyhow::Result<obscura_image::typ::Output> {
+    let options = DecodeOptions {
+        jpeg: JpegOptions { quality },
+        ..output_options(OutputFormat::Jpeg)
+    };
+    encode(&fs::read(path).unwrap(), &options)
+}
+
+#[test]
+fn test_jpeg_output() {
+    for (path, colorspace) in [
+        ("tests/rgb8.tiff", ColorSpace::YCbCr),
+        ("tests/EMD-3197.mrc", ColorSpace::Luma),
+        ("tests/rgb16.tiff", ColorSpace::YCbCr),
+    ] {
+        let output = encode_jpeg(path, 85).unwrap();
+        assert_eq!(format!("{}", output.encoding["quality"]), "85");
+        let image = &output.images[0];
+        assert_eq!(image.format, OutputFormat::Jpeg);
+        assert_eq!(&image.data[..2], [0xFF, 0xD8]);
+
+        let mut decoder = zune_jpeg::JpegDecoder::new(image.data.as_slice());
+        decoder.decode_headers().unwrap();
+        let info = decoder.info().unwrap();
+        assert_eq!(
+            (info.width as u32, info.height as u32),
+            (image.info.width, image.info.height)
+        );
+        assert_eq!(decoder.get_input_colorspace(), Some(colorspace), "{path}");
+    }
+}
+
+#[test]
+fn test_jpeg_quality() {
+    let size = |quality| {
+        encode_jpeg("tests/rgb8.tiff", quality).unwrap().images[0]
+            .data
+            .len()
+    };
+    assert!(size(20) < size(95));
+    assert!(encode_jpeg("tests/rgb8.tiff", 0).is_err());
+}
+
+#[test]
+fn test_jpeg_quality_validated() {
+    let options = |output, quality| DecodeOptions {
+        jpeg: JpegOptions { quality },
+        ..output_options(output)
+    };
+    let error = options(OutputFormat::Jpeg, 101).validate().unwrap_err();
+    assert!(format!("{error}").contains("between 1 and 100"));
+    assert!(options(OutputFormat::Jpeg, 100).validate().is_ok());
+    // Only checked when writing JPEG.
+    assert!(options(OutputFormat::Png, 0).validate().is_ok());
+}
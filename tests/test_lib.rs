@@ -1,6 +1,8 @@
-use obscura_image::encode_result;
+use obscura_image::format::{detect_format, find_format, supported_formats};
+use obscura_image::options::{DecodeOptions, OutputFormat};
 use obscura_image::tiff::decode_tiff;
 use obscura_image::typ::Image;
+use obscura_image::{decode_any, encode_result, encode_result_with_options};
 use std::fs;
 
 #[test]
@@ -45,6 +47,81 @@ fn test_cmyk_unsupported() {
     assert_eq!(msg, "Unsupported TIFF color type: CMYK(8)");
 }
 
+#[test]
+fn test_decode_any() {
+    for (path, format, num_images) in [
+        ("tests/rgb8.tiff", "tiff", 1),
+        ("tests/multipage.tiff", "tiff", 2),
+        ("tests/EMD-3197.mrc", "mrc", 20),
+    ] {
+        let data = fs::read(path).unwrap();
+        let result = decode_any(&data, &DecodeOptions::default()).unwrap();
+        assert_eq!(result.images.len(), num_images, "{path}");
+        let meta = result.metadata.as_ref().unwrap();
+        assert_eq!(format!("{}", meta["format"]), format, "{path}");
+    }
+}
+
+#[test]
+fn test_decode_any_compressed() {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    std::io::Write::write_all(&mut encoder, &fs::read("tests/gray8.tiff").unwrap()).unwrap();
+    let result = decode_any(&encoder.finish().unwrap(), &DecodeOptions::default()).unwrap();
+    let meta = result.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["format"]), "tiff");
+    assert_eq!(format!("{}", meta["compression"]), "gzip");
+}
+
+#[test]
+fn test_decode_any_unknown() {
+    let err = decode_any(&[0u8; 2048], &DecodeOptions::default())
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "Unrecognized image format");
+}
+
+#[test]
+fn test_format_registry() {
+    let names: Vec<&str> = supported_formats().iter().map(|f| f.name).collect();
+    assert_eq!(names, ["tiff", "mrc"]);
+    assert_eq!(find_format("TIFF").unwrap().name(), "tiff");
+    assert!(find_format("jpeg").is_none());
+    let mrc_data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let format = detect_format(&mrc_data).unwrap();
+    assert_eq!(format.name(), "mrc");
+    assert!(format.extensions().contains(&"mrcs"));
+}
+
+#[test]
+fn test_rgba_output() {
+    let options = DecodeOptions {
+        output: OutputFormat::Rgba,
+        ..Default::default()
+    };
+    for path in ["tests/gray8.tiff", "tests/rgb8.tiff", "tests/bilevel.tiff"] {
+        let data = fs::read(path).unwrap();
+        let output =
+            encode_result_with_options(decode_any(&data, &options).unwrap(), &options).unwrap();
+        let image = &output.images[0];
+        assert_eq!(image.format, OutputFormat::Rgba);
+        assert!(image.png_data.is_empty());
+        let (width, height) = (image.info.width as usize, image.info.height as usize);
+        assert_eq!(image.data.len(), width * height * 4, "{path}");
+        assert!(image.data.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    }
+
+    let decoded = decode_tiff(&fs::read("tests/rgb8.tiff").unwrap()).unwrap();
+    let rgb = decoded.images[0].data.clone();
+    let output = encode_result_with_options(decoded, &options).unwrap();
+    for (rgba, rgb) in output.images[0]
+        .data
+        .chunks_exact(4)
+        .zip(rgb.chunks_exact(3))
+    {
+        assert_eq!(&rgba[..3], rgb);
+    }
+}
+
 fn test_tiff(tiff_data: &[u8], num_images: usize, dimensions: (u32, u32)) {
     let decode_result = decode_tiff(tiff_data).unwrap();
     let output = encode_result(decode_result).unwrap();
@@ -1,4 +1,9 @@
-use obscura_image::mrc::decode_mrc;
+mod common;
+
+use common::{f32_bytes, i16_bytes, make_mrc};
+use obscura_image::intensity::IntensityMapping;
+use obscura_image::mrc::{ComplexMapping, StackMode, decode_mrc, decode_mrc_with_options};
+use obscura_image::options::{DecodeOptions, FrameSelection};
 use std::fs;
 
 #[test]
@@ -21,6 +26,8 @@ fn test_mrc_decode() {
     for (key, value) in result_meta {
         println!("File Metadata: {key} = {value}");
     }
+    assert_eq!(format!("{}", result_meta["endianness"]), "Little");
+    assert!(!result_meta.contains_key("warning_0"));
 
     // Should have decoded at least one image
     assert!(
@@ -47,3 +54,315 @@ fn test_mrc_decode() {
         assert_eq!(info.bit_depth, 8, "Should be 8-bit output");
     }
 }
+
+/// A 4x1 Float32Complex slice: 1, i, -1, -i scaled by increasing magnitudes.
+fn complex_mrc() -> Vec<u8> {
+    let values = [1.0, 0.0, 0.0, 10.0, -100.0, 0.0, 0.0, -1000.0];
+    make_mrc(4, 1, 1, 4, &f32_bytes(&values))
+}
+
+fn decode_complex(complex: ComplexMapping) -> obscura_image::typ::DecodedImage {
+    let options = DecodeOptions {
+        complex,
+        ..Default::default()
+    };
+    let result = decode_mrc_with_options(&complex_mrc(), &options).unwrap();
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    let image = result.images.into_iter().next().unwrap();
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["complex_mapping"]), complex.name());
+    image
+}
+
+#[test]
+fn test_complex_magnitude() {
+    let image = decode_complex(ComplexMapping::Magnitude);
+    assert_eq!(image.data, vec![0, 2, 25, 255]);
+}
+
+#[test]
+fn test_complex_log_magnitude() {
+    let image = decode_complex(ComplexMapping::LogMagnitude);
+    assert_eq!(image.data[0], 0);
+    assert_eq!(image.data[3], 255);
+    // Log scaling lifts the small magnitudes well above linear scaling
+    assert!(image.data[1] > 50);
+}
+
+#[test]
+fn test_complex_components() {
+    let real = decode_complex(ComplexMapping::Real);
+    assert_eq!(real.data[2], 0);
+    let imag = decode_complex(ComplexMapping::Imaginary);
+    assert_eq!(imag.data[1], 255);
+    assert_eq!(imag.data[3], 0);
+    let phase = decode_complex(ComplexMapping::Phase);
+    assert!(phase.data[2] > phase.data[1] && phase.data[1] > phase.data[0]);
+}
+
+#[test]
+fn test_complex_phase_hue() {
+    let image = decode_complex(ComplexMapping::PhaseHue);
+    assert_eq!(image.color_type, png::ColorType::Rgb);
+    assert_eq!(image.data.len(), 4 * 3);
+    // Phase 0 is red; the last pixel has full value and phase -90 degrees (violet)
+    assert_eq!(&image.data[9..12], &[127, 0, 255]);
+}
+
+#[test]
+fn test_complex_phase_window() {
+    // Phase is mapped over -pi..pi rather than the range found in the slice.
+    let image = decode_complex(ComplexMapping::Phase);
+    assert_eq!(image.data[2], 255);
+    assert!((63..=64).contains(&image.data[3]));
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["intensity_mapping"]), "window");
+}
+
+#[test]
+fn test_complex_phase_hue_composites() {
+    let values = [1.0, 0.0, 0.0, 10.0, -100.0, 0.0, 0.0, -1000.0];
+    let mrc = make_mrc(4, 1, 2, 4, &f32_bytes(&values.repeat(2)));
+    for (stack, width) in [
+        (StackMode::Average, 4),
+        (StackMode::Montage { columns: Some(2) }, 8),
+    ] {
+        let options = DecodeOptions {
+            complex: ComplexMapping::PhaseHue,
+            stack,
+            ..Default::default()
+        };
+        let result = decode_mrc_with_options(&mrc, &options).unwrap();
+        let image = &result.images[0];
+        assert_eq!(image.color_type, png::ColorType::Rgb);
+        assert_eq!(image.data.len(), width * 3);
+        assert_eq!(&image.data[9..12], &[127, 0, 255]);
+        let meta = image.info.metadata.as_ref().unwrap();
+        assert_eq!(format!("{}", meta["complex_mapping"]), "phase_hue");
+    }
+}
+
+/// A 16x16 Int16 slice occupying a narrow band, as real cryo-EM data tends to.
+fn narrow_int16_mrc() -> Vec<u8> {
+    let values: Vec<i16> = (0..256).map(|v| 1000 + v as i16).collect();
+    make_mrc(16, 16, 1, 1, &i16_bytes(&values))
+}
+
+#[test]
+fn test_int16_uses_data_range() {
+    let result = decode_mrc(&narrow_int16_mrc()).unwrap();
+    let image = &result.images[0];
+    assert_eq!(image.data[0], 0);
+    assert_eq!(image.data[255], 255);
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["min_value"]), "1000");
+    assert_eq!(format!("{}", meta["max_value"]), "1255");
+}
+
+#[test]
+fn test_int8_uses_data_range() {
+    let values: Vec<u8> = (-8i8..8).map(|v| v as u8).collect();
+    let result = decode_mrc(&make_mrc(4, 4, 1, 0, &values)).unwrap();
+    let image = &result.images[0];
+    assert_eq!(image.data[0], 0);
+    assert_eq!(image.data[15], 255);
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["min_value"]), "-8");
+    assert_eq!(format!("{}", meta["max_value"]), "7");
+}
+
+#[test]
+fn test_int16_fixed_window() {
+    let options = DecodeOptions {
+        intensity: Some(IntensityMapping::Window {
+            min: i16::MIN as f32,
+            max: i16::MAX as f32,
+        }),
+        ..Default::default()
+    };
+    let result = decode_mrc_with_options(&narrow_int16_mrc(), &options).unwrap();
+    let image = &result.images[0];
+    // The narrow band is flat grey when mapped over the whole Int16 range
+    assert!(image.data.iter().all(|&v| v == 131 || v == 132));
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["intensity_mapping"]), "window");
+}
+
+/// A stack of six 2x2 Float32 frames, frame `z` filled with the value `z`.
+fn stack_mrc() -> Vec<u8> {
+    let values: Vec<f32> = (0..6).flat_map(|z| [z as f32; 4]).collect();
+    make_mrc(2, 2, 6, 2, &f32_bytes(&values))
+}
+
+fn decode_stack(frames: FrameSelection, stack: StackMode) -> obscura_image::typ::DecodeResult {
+    let options = DecodeOptions {
+        frames,
+        stack,
+        ..Default::default()
+    };
+    let result = decode_mrc_with_options(&stack_mrc(), &options).unwrap();
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    result
+}
+
+#[test]
+fn test_frame_selection() {
+    let frames = FrameSelection {
+        start: 1,
+        end: Some(100),
+        step: 2,
+    };
+    let result = decode_stack(frames, StackMode::Slices);
+    let indices: Vec<usize> = result.images.iter().map(|i| i.info.image_index).collect();
+    assert_eq!(indices, vec![1, 3, 5]);
+}
+
+#[test]
+fn test_frame_average() {
+    let frames = FrameSelection {
+        start: 2,
+        end: Some(5),
+        step: 1,
+    };
+    let result = decode_stack(frames, StackMode::Average);
+    assert_eq!(result.images.len(), 1);
+    let meta = result.images[0].info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["frame_count"]), "3");
+    assert_eq!(format!("{}", meta["min_value"]), "3");
+}
+
+#[test]
+fn test_montage() {
+    let frames = FrameSelection {
+        end: Some(5),
+        ..Default::default()
+    };
+    let result = decode_stack(frames, StackMode::Montage { columns: None });
+    assert_eq!(result.images.len(), 1);
+    let image = &result.images[0];
+    assert_eq!((image.width, image.height), (6, 4));
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["montage_columns"]), "3");
+    assert_eq!(format!("{}", meta["montage_rows"]), "2");
+    // Row 0 holds frames 0, 1, 2; frame 4 is the brightest and the last cell is empty
+    assert_eq!(&image.data[0..6], &[0, 0, 63, 63, 127, 127]);
+    assert_eq!(&image.data[12..18], &[191, 191, 255, 255, 0, 0]);
+}
+
+fn set_i32(mrc: &mut [u8], offset: usize, value: i32) {
+    mrc[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn decode_error(mrc: &[u8]) -> String {
+    decode_mrc(mrc)
+        .err()
+        .expect("expected decoding to fail")
+        .to_string()
+}
+
+#[test]
+fn test_invalid_dimensions() {
+    let mut mrc = stack_mrc();
+    set_i32(&mut mrc, 0, -2);
+    assert_eq!(
+        decode_error(&mrc),
+        "Invalid MRC header: nx must be positive, got -2"
+    );
+
+    let mut mrc = stack_mrc();
+    set_i32(&mut mrc, 0, i32::MAX);
+    set_i32(&mut mrc, 4, i32::MAX);
+    assert!(decode_error(&mrc).contains("slice does not fit in the file"));
+}
+
+#[test]
+fn test_invalid_extended_header() {
+    let mut mrc = stack_mrc();
+    set_i32(&mut mrc, 92, 1 << 20);
+    assert!(decode_error(&mrc).contains("extends beyond the end of the file"));
+
+    let mut mrc = stack_mrc();
+    set_i32(&mut mrc, 92, -4);
+    assert!(decode_error(&mrc).contains("negative extended header size"));
+}
+
+#[test]
+fn test_odd_extended_header() {
+    // A 1-byte extended header leaves the voxel data unaligned.
+    let mut mrc = make_mrc(2, 1, 1, 2, &f32_bytes(&[1.0, 2.0]));
+    set_i32(&mut mrc, 92, 1);
+    mrc.insert(1024, 0);
+    let result = decode_mrc(&mrc).unwrap();
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.images[0].data, vec![0, 255]);
+
+    let mut mrc = make_mrc(2, 1, 1, 1, &i16_bytes(&[-5, 5]));
+    set_i32(&mut mrc, 92, 3);
+    mrc.splice(1024..1024, [0; 3]);
+    let result = decode_mrc(&mrc).unwrap();
+    let meta = result.images[0].info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["min_value"]), "-5");
+}
+
+#[test]
+fn test_unknown_mode() {
+    let mut mrc = stack_mrc();
+    set_i32(&mut mrc, 12, 42);
+    assert_eq!(decode_error(&mrc), "Unknown MRC mode: 42");
+}
+
+#[test]
+fn test_truncated_file_warning() {
+    let mut mrc = stack_mrc();
+    mrc.truncate(mrc.len() - 20);
+    let result = decode_mrc(&mrc).unwrap();
+    assert_eq!(result.images.len(), 4);
+    assert_eq!(result.errors.len(), 2);
+    let meta = result.metadata.as_ref().unwrap();
+    assert_eq!(
+        format!("{}", meta["warning_0"]),
+        "File is truncated: header declares 6 slices, but only 4 are present"
+    );
+}
+
+#[test]
+fn test_machine_stamp() {
+    for (machst, endianness) in [
+        ([0x44, 0x44, 0, 0], "Little"),
+        ([0x44, 0x41, 0, 0], "Little"),
+        ([0x11, 0x11, 0, 0], "Big"),
+        ([0x12, 0x34, 0, 0], "Unknown(0x12340000)"),
+    ] {
+        let mut mrc = stack_mrc();
+        mrc[212..216].copy_from_slice(&machst);
+        let result = decode_mrc(&mrc).unwrap();
+        let meta = result.metadata.as_ref().unwrap();
+        assert_eq!(format!("{}", meta["endianness"]), endianness);
+        assert_eq!(
+            meta.contains_key("warning_0"),
+            endianness.starts_with("Unknown")
+        );
+    }
+}
+
+#[test]
+fn test_header_warnings() {
+    let mut mrc = stack_mrc();
+    mrc[208..212].copy_from_slice(b"    ");
+    set_i32(&mut mrc, 96 + 12, 12345);
+    set_i32(&mut mrc, 220, 50);
+    let result = decode_mrc(&mrc).unwrap();
+    assert_eq!(result.images.len(), 6);
+    let meta = result.metadata.as_ref().unwrap();
+    let warnings: Vec<String> = (0..3)
+        .map(|n| format!("{}", meta[&format!("warning_{n}")]))
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "Missing MAP signature (found \"    \")",
+            "Unknown NVERSION 12345",
+            "Invalid label count 50, reading at most 10 labels",
+        ]
+    );
+}
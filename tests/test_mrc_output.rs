@@ -0,0 +1,114 @@
+mod common;
+
+use common::{encode, f32_bytes, i16_bytes, make_mrc, output_options};
+use obscura_image::mrc::decode_mrc_with_options;
+use obscura_image::options::{DecodeOptions, OutputFormat};
+use obscura_image::typ::SampleType;
+use std::fs;
+use std::io::Cursor;
+use tiff::encoder::{TiffEncoder, colortype};
+
+fn to_mrc(data: &[u8]) -> anyhow::Result<obscura_image::typ::Output> {
+    encode(data, &output_options(OutputFormat::Mrc))
+}
+
+fn header_f32(data: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn header_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_mrc_from_tiff_stack() {
+    let mut buf = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut buf)).unwrap();
+    encoder
+        .write_image::<colortype::Gray16>(3, 2, &[0, 1, 2, 3, 4, 5])
+        .unwrap();
+    encoder
+        .write_image::<colortype::Gray16>(3, 2, &[6, 7, 8, 9, 10, 65535])
+        .unwrap();
+
+    let output = to_mrc(&buf).unwrap();
+    assert_eq!(output.images.len(), 1);
+    let image = &output.images[0];
+    assert_eq!(image.format, OutputFormat::Mrc);
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["slice_count"]), "2");
+
+    let mrc = &image.data;
+    assert_eq!(mrc.len(), 1024 + 2 * 6 * 2);
+    assert_eq!(
+        [0, 4, 8, 12].map(|offset| header_i32(mrc, offset)),
+        [3, 2, 2, 6]
+    );
+    assert_eq!(&mrc[208..212], b"MAP ");
+    assert_eq!(header_i32(mrc, 108), 20140);
+    assert_eq!(header_f32(mrc, 76), 0.0);
+    assert_eq!(header_f32(mrc, 80), 65535.0);
+    assert!(String::from_utf8_lossy(&mrc[224..304]).starts_with("Written by obscura-image"));
+
+    let options = DecodeOptions {
+        samples: true,
+        ..Default::default()
+    };
+    let decoded = decode_mrc_with_options(mrc, &options).unwrap();
+    assert_eq!(decoded.images.len(), 2);
+    let samples = decoded.images[1].samples.as_ref().unwrap();
+    assert_eq!(samples.values::<u16>(), [6, 7, 8, 9, 10, 65535]);
+}
+
+#[test]
+fn test_mrc_roundtrip() {
+    let values = [-1.5, 0.0, 0.5, 4.0, 2.0, 1.0];
+    let data = make_mrc(3, 1, 2, 2, &f32_bytes(&values));
+    let output = to_mrc(&data).unwrap();
+    let mrc = &output.images[0].data;
+    assert_eq!(&mrc[1024..], f32_bytes(&values));
+    // Stats over all sections; make_mrc has 1 Å pixels.
+    assert_eq!(header_f32(mrc, 76), -1.5);
+    assert_eq!(header_f32(mrc, 80), 4.0);
+    assert_eq!(header_f32(mrc, 84), 1.0);
+    assert_eq!(header_f32(mrc, 40), 3.0);
+
+    let complex = make_mrc(2, 1, 1, 3, &i16_bytes(&[3, 4, 0, 0]));
+    let mrc = &to_mrc(&complex).unwrap().images[0].data;
+    assert_eq!(header_i32(mrc, 12), 3);
+    assert_eq!(header_f32(mrc, 80), 5.0);
+}
+
+#[test]
+fn test_mrc_widens_uint8() {
+    let data = fs::read("tests/gray8.tiff").unwrap();
+    let output = to_mrc(&data).unwrap();
+    let mrc = &output.images[0].data;
+    // MRC 2014 has no unsigned 8-bit mode, so the data is written as uint16.
+    assert_eq!(header_i32(mrc, 12), 6);
+    let (width, height) = (header_i32(mrc, 0) as usize, header_i32(mrc, 4) as usize);
+    assert_eq!(mrc.len(), 1024 + width * height * 2);
+
+    let options = DecodeOptions {
+        samples: true,
+        ..Default::default()
+    };
+    let original = obscura_image::decode_any(&data, &options).unwrap();
+    let original = original.images[0].samples.as_ref().unwrap().values::<u8>();
+    let decoded = decode_mrc_with_options(mrc, &options).unwrap();
+    let samples = decoded.images[0].samples.as_ref().unwrap();
+    assert_eq!(samples.dtype, SampleType::Uint16);
+    let widened: Vec<u16> = original.iter().map(|&v| v.into()).collect();
+    assert_eq!(samples.values::<u16>(), widened);
+    let max = original.iter().max().copied().unwrap();
+    assert_eq!(header_f32(mrc, 80), f32::from(max));
+}
+
+#[test]
+fn test_mrc_refuses_color() {
+    let data = fs::read("tests/rgb8.tiff").unwrap();
+    let Err(error) = to_mrc(&data) else {
+        panic!("Expected RGB data to be refused");
+    };
+    assert!(format!("{error}").contains("3 channels"));
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{f32_bytes, make_mrc};
+use obscura_image::intensity::IntensityMapping;
+use obscura_image::mrc::{ComplexMapping, StackMode};
+use obscura_image::options::{DecodeOptions, FrameSelection, MetadataLevel};
+use obscura_image::{decode_any, encode_result_with_options};
+use std::fs;
+
+fn parse(json: &str) -> DecodeOptions {
+    serde_json::from_str(json).expect("Failed to parse options")
+}
+
+#[test]
+fn test_options_defaults() {
+    let options = parse("{}");
+    assert_eq!(options.intensity, None);
+    assert_eq!(options.frames, FrameSelection::default());
+    assert_eq!(options.metadata, MetadataLevel::Full);
+    assert_eq!(
+        options.max_decompressed_size,
+        DecodeOptions::default().max_decompressed_size
+    );
+}
+
+#[test]
+fn test_options_deserialize() {
+    let options = parse(
+        r#"{
+            "intensity": {"mode": "percentile", "low": 1},
+            "complex": "log_magnitude",
+            "frames": {"start": 2, "step": 3},
+            "stack": {"mode": "montage", "columns": 4},
+            "max_width": 1024,
+            "output": "png",
+            "metadata": "basic"
+        }"#,
+    );
+    assert_eq!(
+        options.intensity,
+        Some(IntensityMapping::Percentile {
+            low: 1.0,
+            high: 99.5
+        })
+    );
+    assert_eq!(options.complex, ComplexMapping::LogMagnitude);
+    assert_eq!(options.frames.start, 2);
+    assert_eq!(options.frames.end, None);
+    assert_eq!(options.frames.step, 3);
+    assert_eq!(options.stack, StackMode::Montage { columns: Some(4) });
+    assert_eq!(options.max_width, Some(1024));
+    assert_eq!(options.max_height, None);
+    assert_eq!(options.metadata, MetadataLevel::Basic);
+
+    assert!(serde_json::from_str::<DecodeOptions>(r#"{"output": "gif"}"#).is_err());
+}
+
+#[test]
+fn test_options_unknown_fields() {
+    // Misspelt options are errors rather than silently ignored.
+    for json in [
+        r#"{"intesity": {"mode": "equalize"}}"#,
+        r#"{"intensity": {"mode": "percentile", "lo": 1}}"#,
+        r#"{"stack": {"mode": "montage", "colums": 4}}"#,
+        r#"{"frames": {"begin": 2}}"#,
+        r#"{"png": {"compresion": "best"}}"#,
+        r#"{"thumbnail": {"max_widht": 64}}"#,
+        r#"{"contact_sheet": {"tilesize": 64}}"#,
+    ] {
+        let error = serde_json::from_str::<DecodeOptions>(json).err();
+        assert!(
+            error.is_some_and(|e| e.to_string().starts_with("unknown field")),
+            "{json}"
+        );
+    }
+}
+
+#[test]
+fn test_tiff_page_selection() {
+    let data = fs::read("tests/multipage.tiff").unwrap();
+    let options = DecodeOptions {
+        frames: FrameSelection {
+            start: 1,
+            end: None,
+            step: 1,
+        },
+        ..Default::default()
+    };
+    let result = decode_any(&data, &options).unwrap();
+    assert_eq!(result.images.len(), 1);
+    assert_eq!(result.images[0].info.image_index, 1);
+    assert!(result.errors.is_empty());
+}
+
+#[test]
+fn test_max_dimensions() {
+    let data = make_mrc(8, 4, 2, 2, &f32_bytes(&[0.0; 64]));
+    let options = DecodeOptions {
+        max_width: Some(6),
+        ..Default::default()
+    };
+    let result = decode_any(&data, &options).unwrap();
+    assert!(result.images.is_empty());
+    assert_eq!(result.errors.len(), 2);
+    assert!(
+        result.errors[0]
+            .message
+            .contains("Image size 8x4 exceeds the maximum of 6xany")
+    );
+
+    let tiff = fs::read("tests/rgb8.tiff").unwrap();
+    let options = DecodeOptions {
+        max_height: Some(32),
+        ..Default::default()
+    };
+    let output =
+        encode_result_with_options(decode_any(&tiff, &options).unwrap(), &options).unwrap();
+    assert!(output.images.is_empty());
+    assert_eq!(output.total_images, 1);
+}
+
+#[test]
+fn test_metadata_levels() {
+    let data = make_mrc(4, 4, 1, 2, &f32_bytes(&[1.0; 16]));
+    let decode = |metadata| {
+        let options = DecodeOptions {
+            metadata,
+            ..Default::default()
+        };
+        decode_any(&data, &options).unwrap()
+    };
+
+    let full = decode(MetadataLevel::Full);
+    let meta = full.metadata.as_ref().unwrap();
+    assert!(meta.contains_key("endianness"));
+    assert_eq!(format!("{}", meta["format"]), "mrc");
+
+    let basic = decode(MetadataLevel::Basic);
+    let meta = basic.metadata.as_ref().unwrap();
+    assert!(!meta.contains_key("endianness"));
+    assert_eq!(format!("{}", meta["format"]), "mrc");
+    assert!(basic.images[0].info.metadata.is_some());
+
+    let none = decode(MetadataLevel::None);
+    assert!(none.metadata.is_none());
+    assert!(none.images[0].info.metadata.is_none());
+}
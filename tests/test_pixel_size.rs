@@ -0,0 +1,111 @@
+mod common;
+
+use common::{f32_bytes, make_mrc};
+use obscura_image::mrc::decode_mrc;
+use obscura_image::tiff::decode_tiff;
+use std::fs;
+use std::io::Cursor;
+use tiff::encoder::{Rational, TiffEncoder, colortype::Gray8};
+use tiff::tags::{ResolutionUnit, Tag};
+
+/// Encode a small grayscale TIFF with the given resolution and optional description.
+fn make_tiff(unit: ResolutionUnit, resolution: u32, description: Option<&str>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut buf)).unwrap();
+    let mut image = encoder.new_image::<Gray8>(4, 4).unwrap();
+    image.resolution(
+        unit,
+        Rational {
+            n: resolution,
+            d: 1,
+        },
+    );
+    if let Some(description) = description {
+        image
+            .encoder()
+            .write_tag(Tag::ImageDescription, description)
+            .unwrap();
+    }
+    image.write_data(&[0u8; 16]).unwrap();
+    buf
+}
+
+fn pixel_size(tiff_data: &[u8]) -> Vec<String> {
+    let result = decode_tiff(tiff_data).unwrap();
+    let meta = result.images[0].info.metadata.as_ref().unwrap();
+    [
+        "pixel_size_x",
+        "pixel_size_y",
+        "pixel_size_z",
+        "pixel_size_unit",
+        "pixel_size_source",
+    ]
+    .iter()
+    .map(|key| meta.get(*key).map_or("-".to_string(), |v| format!("{v}")))
+    .collect()
+}
+
+#[test]
+fn test_tiff_resolution_inch() {
+    let tiff_data = make_tiff(ResolutionUnit::Inch, 200, None);
+    assert_eq!(
+        pixel_size(&tiff_data),
+        ["0.005", "0.005", "-", "inch", "tiff_resolution"]
+    );
+}
+
+#[test]
+fn test_tiff_resolution_centimeter() {
+    let tiff_data = make_tiff(ResolutionUnit::Centimeter, 1000, None);
+    assert_eq!(
+        pixel_size(&tiff_data),
+        ["10", "10", "-", "µm", "tiff_resolution"]
+    );
+}
+
+#[test]
+fn test_tiff_without_resolution_unit() {
+    let tiff_data = make_tiff(ResolutionUnit::None, 1, None);
+    let result = decode_tiff(&tiff_data).unwrap();
+    assert!(result.images[0].info.metadata.is_none());
+}
+
+#[test]
+fn test_imagej_calibration() {
+    let description = "ImageJ=1.54f\nimages=1\nunit=micron\nspacing=2.5\n";
+    let tiff_data = make_tiff(ResolutionUnit::None, 4, Some(description));
+    assert_eq!(
+        pixel_size(&tiff_data),
+        ["0.25", "0.25", "2.5", "µm", "imagej"]
+    );
+}
+
+#[test]
+fn test_ome_physical_size() {
+    let description = r#"<?xml version="1.0"?><OME><Image ID="Image:0"><Pixels ID="Pixels:0" PhysicalSizeX="0.65" PhysicalSizeY="0.65" PhysicalSizeZ="2000" PhysicalSizeZUnit="nm" SizeX="4" SizeY="4"/></Image></OME>"#;
+    let tiff_data = make_tiff(ResolutionUnit::Inch, 72, Some(description));
+    assert_eq!(pixel_size(&tiff_data), ["0.65", "0.65", "2", "µm", "ome"]);
+}
+
+#[test]
+fn test_mrc_voxel_size() {
+    let mut mrc = make_mrc(2, 2, 1, 2, &f32_bytes(&[0.0; 4]));
+    // Cell of 3 x 5 x 7 Å sampled 2 x 2 x 1 times
+    for (offset, value) in [(40, 3.0f32), (44, 5.0), (48, 7.0)] {
+        mrc[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    let result = decode_mrc(&mrc).unwrap();
+    let meta = result.images[0].info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["pixel_size_x"]), "1.5");
+    assert_eq!(format!("{}", meta["pixel_size_y"]), "2.5");
+    assert_eq!(format!("{}", meta["pixel_size_z"]), "7");
+    assert_eq!(format!("{}", meta["pixel_size_unit"]), "Å");
+}
+
+#[test]
+fn test_emdb_voxel_size() {
+    let result = decode_mrc(&fs::read("tests/EMD-3197.mrc").unwrap()).unwrap();
+    let meta = result.images[0].info.metadata.as_ref().unwrap();
+    assert!(meta.contains_key("pixel_size_x"));
+    assert_eq!(format!("{}", meta["pixel_size_source"]), "mrc_header");
+}
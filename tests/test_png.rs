@@ -0,0 +1,288 @@
+mod common;
+
+use common::{encode_first, i16_bytes, make_mrc};
+use obscura_image::options::{
+    ApngOptions, DecodeOptions, OutputFormat, PngCompression, PngFilter, PngOptions,
+};
+use obscura_image::{decode_any, encode_result_with_options};
+use std::fs;
+use std::io::Cursor;
+use tiff::encoder::colortype::{Gray8, Gray16, RGB8};
+use tiff::encoder::{Rational, TiffEncoder};
+use tiff::tags::{ResolutionUnit, Tag};
+
+/// Read back a PNG, returning its header info and pixel data.
+fn read_png(data: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+    let mut reader = png::Decoder::new(data).read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    buffer.truncate(info.buffer_size());
+    (info, buffer)
+}
+
+fn preserve_depth() -> DecodeOptions {
+    DecodeOptions {
+        preserve_depth: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_8bit_by_default() {
+    let data = fs::read("tests/rgb16.tiff").unwrap();
+    let image = encode_first(&data, &DecodeOptions::default());
+    let (info, _) = read_png(&image.png_data);
+    assert_eq!(info.bit_depth, png::BitDepth::Eight);
+}
+
+#[test]
+fn test_gray_tiff_stays_gray() {
+    let data = fs::read("tests/gray8.tiff").unwrap();
+    let image = encode_first(&data, &DecodeOptions::default());
+    let (info, pixels) = read_png(&image.png_data);
+    assert_eq!(info.color_type, png::ColorType::Grayscale);
+    assert_eq!(pixels.len(), 64 * 64);
+
+    let mut buf = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut buf)).unwrap();
+    encoder.write_image::<Gray16>(2, 1, &[0, 65535]).unwrap();
+    let image = encode_first(&buf, &DecodeOptions::default());
+    let (info, pixels) = read_png(&image.png_data);
+    assert_eq!(info.color_type, png::ColorType::Grayscale);
+    assert_eq!(info.bit_depth, png::BitDepth::Eight);
+    assert_eq!(pixels, [0, 255]);
+}
+
+#[test]
+fn test_16bit_tiff() {
+    let data = fs::read("tests/rgb16.tiff").unwrap();
+    let image = encode_first(&data, &preserve_depth());
+    let (info, pixels) = read_png(&image.png_data);
+    assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+
+    let mut decoder = tiff::decoder::Decoder::new(std::io::Cursor::new(&data)).unwrap();
+    let tiff::decoder::DecodingResult::U16(expected) = decoder.read_image().unwrap() else {
+        panic!("Expected 16-bit samples");
+    };
+    let values: Vec<u16> = pixels
+        .chunks_exact(2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn test_16bit_mrc() {
+    let data = make_mrc(3, 1, 1, 1, &i16_bytes(&[-32768, 0, 1000]));
+    let image = encode_first(&data, &preserve_depth());
+    assert_eq!(image.info.bit_depth, 16);
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["value_offset"]), "32768");
+    assert_eq!(format!("{}", meta["min_value"]), "-32768");
+
+    let (info, pixels) = read_png(&image.png_data);
+    assert_eq!(info.bit_depth, png::BitDepth::Sixteen);
+    assert_eq!(info.color_type, png::ColorType::Grayscale);
+    assert_eq!(pixels, [0, 0, 0x80, 0, 0x83, 0xE8]);
+}
+
+#[test]
+fn test_16bit_rgba_output() {
+    let data = fs::read("tests/rgb16.tiff").unwrap();
+    let options = DecodeOptions {
+        output: OutputFormat::Rgba,
+        ..preserve_depth()
+    };
+    let image = encode_first(&data, &options);
+    assert_eq!(image.data.len(), 64 * 64 * 4);
+}
+
+#[test]
+fn test_png_compression() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let encode = |png: PngOptions| {
+        let options = DecodeOptions {
+            png,
+            ..Default::default()
+        };
+        let result = decode_any(&data, &options).unwrap();
+        encode_result_with_options(result, &options).unwrap()
+    };
+    let fast = encode(PngOptions::default());
+    let best = encode(PngOptions {
+        compression: PngCompression::Best,
+        filter: PngFilter::Adaptive,
+        ..Default::default()
+    });
+
+    let size = |output: &obscura_image::typ::Output| -> usize {
+        output.images.iter().map(|image| image.png_data.len()).sum()
+    };
+    assert!(size(&best) < size(&fast));
+    for (fast, best) in fast.images.iter().zip(&best.images) {
+        assert_eq!(read_png(&fast.png_data).1, read_png(&best.png_data).1);
+    }
+
+    assert_eq!(format!("{}", fast.encoding["compression"]), "fast");
+    assert_eq!(format!("{}", best.encoding["format"]), "png");
+    assert_eq!(format!("{}", best.encoding["compression"]), "best");
+    assert_eq!(format!("{}", best.encoding["filter"]), "adaptive");
+}
+
+fn embed_metadata() -> DecodeOptions {
+    DecodeOptions {
+        png: PngOptions {
+            embed_metadata: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// The iTXt chunks of a PNG as key/value pairs.
+fn png_text(data: &[u8]) -> Vec<(String, String)> {
+    let reader = png::Decoder::new(data).read_info().unwrap();
+    reader
+        .info()
+        .utf8_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.get_text().unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_no_metadata_by_default() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let image = encode_first(&data, &DecodeOptions::default());
+    assert!(png_text(&image.png_data).is_empty());
+}
+
+#[test]
+fn test_embed_mrc_metadata() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let image = encode_first(&data, &embed_metadata());
+    let text = png_text(&image.png_data);
+    let get = |key: &str| {
+        text.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .unwrap()
+    };
+    assert_eq!(get("format"), "mrc");
+    assert_eq!(get("pixel_size_unit"), "Å");
+    assert!(get("label_0").contains("EMD-3197"));
+    assert!(text.iter().any(|(k, _)| k == "min_value"));
+
+    let pixel_size: f64 = get("pixel_size_x").parse().unwrap();
+    let reader = png::Decoder::new(image.png_data.as_slice())
+        .read_info()
+        .unwrap();
+    let dims = reader.info().pixel_dims.unwrap();
+    assert_eq!(dims.xppu, (1e10 / pixel_size).round() as u32);
+}
+
+#[test]
+fn test_embed_tiff_resolution_and_profile() {
+    let mut profile = vec![0u8; 128];
+    profile[16..20].copy_from_slice(b"RGB ");
+
+    let mut buf = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut buf)).unwrap();
+    let mut image = encoder.new_image::<RGB8>(4, 4).unwrap();
+    image.resolution(ResolutionUnit::Centimeter, Rational { n: 100, d: 1 });
+    image
+        .encoder()
+        .write_tag(Tag::ImageDescription, "A test image")
+        .unwrap();
+    image
+        .encoder()
+        .write_tag(Tag::IccProfile, profile.as_slice())
+        .unwrap();
+    image.write_data(&[0u8; 48]).unwrap();
+
+    let image = encode_first(&buf, &embed_metadata());
+    let text = png_text(&image.png_data);
+    assert!(text.contains(&("description".to_string(), "A test image".to_string())));
+
+    let reader = png::Decoder::new(image.png_data.as_slice())
+        .read_info()
+        .unwrap();
+    let info = reader.info();
+    let dims = info.pixel_dims.unwrap();
+    assert_eq!((dims.xppu, dims.yppu), (10000, 10000));
+    assert_eq!(dims.unit, png::Unit::Meter);
+    assert_eq!(info.icc_profile.as_deref(), Some(profile.as_slice()));
+}
+
+fn apng(delay_ms: u16) -> DecodeOptions {
+    DecodeOptions {
+        output: OutputFormat::Apng,
+        apng: ApngOptions {
+            delay_ms,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_apng_mrc_stack() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let options = apng(40);
+    let result = decode_any(&data, &options).unwrap();
+    let frame_count = result.images.len();
+    let output = encode_result_with_options(result, &options).unwrap();
+    assert_eq!(output.images.len(), 1);
+    assert_eq!(output.total_images, 1);
+    assert_eq!(format!("{}", output.encoding["delay_ms"]), "40");
+
+    let image = &output.images[0];
+    assert_eq!(image.format, OutputFormat::Apng);
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["frame_count"]), frame_count.to_string());
+
+    let mut reader = png::Decoder::new(image.png_data.as_slice())
+        .read_info()
+        .unwrap();
+    let control = reader.info().animation_control.unwrap();
+    assert_eq!(control.num_frames as usize, frame_count);
+    assert_eq!(control.num_plays, 0);
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buffer).unwrap();
+    let frame = reader.info().frame_control.unwrap();
+    assert_eq!((frame.delay_num, frame.delay_den), (40, 1000));
+}
+
+#[test]
+fn test_apng_mismatched_frames() {
+    let mut buf = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut buf)).unwrap();
+    encoder.write_image::<RGB8>(4, 4, &[0u8; 48]).unwrap();
+    encoder.write_image::<RGB8>(2, 2, &[0u8; 12]).unwrap();
+
+    let options = apng(100);
+    let result = decode_any(&buf, &options).unwrap();
+    assert_eq!(result.images.len(), 2);
+    let Err(error) = encode_result_with_options(result, &options) else {
+        panic!("Expected mismatched frames to fail");
+    };
+    assert!(format!("{error}").contains("image 1 is 2x2"));
+
+    // Pages that only differ in depth
+    let mut buf = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut buf)).unwrap();
+    encoder.write_image::<Gray8>(4, 4, &[0u8; 16]).unwrap();
+    encoder.write_image::<Gray16>(4, 4, &[0u16; 16]).unwrap();
+    let options = DecodeOptions {
+        preserve_depth: true,
+        ..apng(100)
+    };
+    let result = decode_any(&buf, &options).unwrap();
+    let Err(error) = encode_result_with_options(result, &options) else {
+        panic!("Expected mismatched frames to fail");
+    };
+    assert!(
+        format!("{error}").ends_with("(4x4 8-bit Grayscale), but image 1 is 4x4 16-bit Grayscale")
+    );
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use common::{f32_bytes, i16_bytes, make_mrc};
+use obscura_image::mrc::{StackMode, decode_mrc_with_options};
+use obscura_image::options::DecodeOptions;
+use obscura_image::typ::ImageDecodeError;
+use obscura_image::{decode_any, probe_with_options};
+use std::fs;
+use std::io::Write;
+
+#[test]
+fn test_probe_tiff() {
+    let data = fs::read("tests/multipage.tiff").unwrap();
+    let result = probe_with_options(&data, None, &DecodeOptions::default()).unwrap();
+    assert_eq!(result.total_images, 2);
+    assert_eq!(
+        format!("{}", result.metadata.as_ref().unwrap()["format"]),
+        "tiff"
+    );
+
+    let decoded = decode_any(&data, &DecodeOptions::default()).unwrap();
+    for (info, image) in result.images.iter().zip(&decoded.images) {
+        assert_eq!(info.image_index, image.info.image_index);
+        assert_eq!((info.width, info.height), (image.width, image.height));
+        assert_eq!(info.color_type, image.info.color_type);
+        assert_eq!(info.bit_depth, image.info.bit_depth);
+    }
+}
+
+#[test]
+fn test_probe_unsupported_color_type() {
+    // Pages that can't be decoded can still be described.
+    let data = fs::read("tests/cmyk-lzw.tiff").unwrap();
+    let result = probe_with_options(&data, None, &DecodeOptions::default()).unwrap();
+    assert_eq!(result.images.len(), 1);
+    assert_eq!(result.images[0].color_type, "CMYK");
+}
+
+#[test]
+fn test_probe_mrc() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let result = probe_with_options(&data, None, &DecodeOptions::default()).unwrap();
+    assert_eq!(result.total_images, 20);
+    assert!(result.errors.is_empty());
+    let meta = result.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["format"]), "mrc");
+    assert!(meta.contains_key("label_0"));
+    assert!(
+        result.images[0]
+            .metadata
+            .as_ref()
+            .unwrap()
+            .contains_key("pixel_size_x")
+    );
+}
+
+#[test]
+fn test_probe_mrc_montage() {
+    let data = make_mrc(3, 2, 5, 2, &f32_bytes(&[0.0; 30]));
+    let options = DecodeOptions {
+        stack: StackMode::Montage { columns: Some(2) },
+        ..Default::default()
+    };
+    let result = probe_with_options(&data, None, &options).unwrap();
+    let decoded = decode_mrc_with_options(&data, &options).unwrap();
+    assert_eq!(result.images.len(), 1);
+    assert_eq!(
+        (result.images[0].width, result.images[0].height),
+        (decoded.images[0].width, decoded.images[0].height)
+    );
+    assert_eq!((result.images[0].width, result.images[0].height), (6, 6));
+}
+
+#[test]
+fn test_probe_truncated_mrc() {
+    let mut data = make_mrc(4, 4, 3, 2, &f32_bytes(&[0.0; 48]));
+    data.truncate(data.len() - 4);
+    let result = probe_with_options(&data, None, &DecodeOptions::default()).unwrap();
+    assert_eq!(result.images.len(), 2);
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].image_index, 2);
+}
+
+#[test]
+fn test_probe_bit_depth() {
+    // The bit depth is that of the decoded image, for every format.
+    let int16_mrc = make_mrc(3, 1, 1, 1, &i16_bytes(&[-1, 0, 1]));
+    for (data, preserve_depth, bit_depth) in [
+        (fs::read("tests/rgb16.tiff").unwrap(), false, 8),
+        (fs::read("tests/rgb16.tiff").unwrap(), true, 16),
+        (fs::read("tests/bilevel.tiff").unwrap(), true, 8),
+        (int16_mrc.clone(), false, 8),
+        (int16_mrc, true, 16),
+    ] {
+        let options = DecodeOptions {
+            preserve_depth,
+            ..Default::default()
+        };
+        let result = probe_with_options(&data, None, &options).unwrap();
+        let decoded = decode_any(&data, &options).unwrap();
+        assert_eq!(result.images[0].bit_depth, bit_depth);
+        assert_eq!(decoded.images[0].info.bit_depth, bit_depth);
+    }
+}
+
+#[test]
+fn test_probe_size_limits() {
+    // Images decode would refuse are reported as errors, with the same messages.
+    let mrc = make_mrc(3, 2, 5, 2, &f32_bytes(&[0.0; 30]));
+    let cases = [
+        (
+            fs::read("tests/multipage.tiff").unwrap(),
+            StackMode::Slices,
+            1,
+        ),
+        (mrc.clone(), StackMode::Slices, 2),
+        (mrc.clone(), StackMode::Average, 2),
+        (mrc, StackMode::Montage { columns: None }, 8),
+    ];
+    for (data, stack, max_width) in cases {
+        let options = DecodeOptions {
+            stack,
+            max_width: Some(max_width),
+            ..Default::default()
+        };
+        let result = probe_with_options(&data, None, &options).unwrap();
+        let decoded = decode_any(&data, &options).unwrap();
+        assert!(result.images.is_empty(), "{stack:?}");
+        assert!(decoded.images.is_empty(), "{stack:?}");
+        let messages = |errors: &[ImageDecodeError]| -> Vec<String> {
+            errors.iter().map(|e| e.message.clone()).collect()
+        };
+        assert_eq!(messages(&result.errors), messages(&decoded.errors));
+        assert!(result.errors[0].message.contains("exceeds the maximum"));
+    }
+}
+
+#[test]
+fn test_probe_compressed() {
+    let data = fs::read("tests/rgb8.tiff").unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    let result =
+        probe_with_options(&encoder.finish().unwrap(), None, &DecodeOptions::default()).unwrap();
+    assert_eq!(result.images.len(), 1);
+    assert_eq!(
+        format!("{}", result.metadata.as_ref().unwrap()["compression"]),
+        "gzip"
+    );
+}
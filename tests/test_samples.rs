@@ -0,0 +1,94 @@
+mod common;
+
+use common::{f32_bytes, i16_bytes, make_mrc};
+use obscura_image::mrc::{StackMode, decode_mrc_with_options};
+use obscura_image::options::{DecodeOptions, OutputFormat};
+use obscura_image::tiff::{decode_tiff, decode_tiff_with_options};
+use obscura_image::typ::SampleType;
+use obscura_image::{decode_any, encode_result_with_options};
+use std::fs;
+
+fn with_samples() -> DecodeOptions {
+    DecodeOptions {
+        samples: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_no_samples_by_default() {
+    let result = decode_tiff(&fs::read("tests/rgb16.tiff").unwrap()).unwrap();
+    assert!(result.images[0].samples.is_none());
+}
+
+#[test]
+fn test_tiff_samples() {
+    let data = fs::read("tests/rgb16.tiff").unwrap();
+    let result = decode_tiff_with_options(&data, &with_samples()).unwrap();
+    let samples = result.images[0].samples.as_ref().unwrap();
+    assert_eq!(samples.dtype, SampleType::Uint16);
+    assert_eq!(samples.shape, [64, 64, 3]);
+
+    let mut decoder = tiff::decoder::Decoder::new(std::io::Cursor::new(&data)).unwrap();
+    let tiff::decoder::DecodingResult::U16(expected) = decoder.read_image().unwrap() else {
+        panic!("Expected 16-bit samples");
+    };
+    let values: Vec<u16> = samples
+        .data
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn test_mrc_samples() {
+    let values: Vec<f32> = (0..12).map(|v| v as f32 * 0.5 - 1.0).collect();
+    let data = make_mrc(4, 3, 1, 2, &f32_bytes(&values));
+    let result = decode_mrc_with_options(&data, &with_samples()).unwrap();
+    let samples = result.images[0].samples.as_ref().unwrap();
+    assert_eq!(samples.dtype, SampleType::Float32);
+    assert_eq!(samples.shape, [3, 4, 1]);
+    assert_eq!(samples.data, f32_bytes(&values));
+}
+
+#[test]
+fn test_mrc_complex_samples() {
+    let values = [1, -2, 3, -4, 5, -6];
+    let data = make_mrc(3, 1, 1, 3, &i16_bytes(&values));
+    let result = decode_mrc_with_options(&data, &with_samples()).unwrap();
+    let samples = result.images[0].samples.as_ref().unwrap();
+    assert_eq!(samples.dtype, SampleType::Int16);
+    assert_eq!(samples.shape, [1, 3, 2]);
+    assert_eq!(samples.data, i16_bytes(&values));
+}
+
+#[test]
+fn test_average_samples() {
+    let values = [0.0, 2.0, 4.0, 6.0];
+    let data = make_mrc(2, 1, 2, 2, &f32_bytes(&values));
+    let options = DecodeOptions {
+        stack: StackMode::Average,
+        ..with_samples()
+    };
+    let result = decode_mrc_with_options(&data, &options).unwrap();
+    let samples = result.images[0].samples.as_ref().unwrap();
+    assert_eq!(samples.shape, [1, 2, 1]);
+    assert_eq!(samples.data, f32_bytes(&[2.0, 4.0]));
+}
+
+#[test]
+fn test_samples_only() {
+    let options = DecodeOptions {
+        output: OutputFormat::None,
+        ..with_samples()
+    };
+    let data = fs::read("tests/gray8.tiff").unwrap();
+    let output =
+        encode_result_with_options(decode_any(&data, &options).unwrap(), &options).unwrap();
+    let image = &output.images[0];
+    assert!(image.data.is_empty() && image.png_data.is_empty());
+    let samples = image.samples.as_ref().unwrap();
+    assert_eq!(samples.dtype, SampleType::Uint8);
+    assert_eq!(samples.data.len(), 64 * 64);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{encode_first, f32_bytes, make_mrc, output_options};
+use obscura_image::options::{DecodeOptions, OutputFormat, ResampleFilter, ThumbnailOptions};
+use std::fs;
+
+fn thumbnail(max_width: u32, max_height: u32, filter: ResampleFilter) -> DecodeOptions {
+    DecodeOptions {
+        thumbnail: Some(ThumbnailOptions {
+            max_width: Some(max_width),
+            max_height: Some(max_height),
+            filter,
+        }),
+        ..output_options(OutputFormat::Rgba)
+    }
+}
+
+/// The red channel of RGBA pixels.
+fn red(image: &obscura_image::typ::Image) -> Vec<u8> {
+    image.data.chunks_exact(4).map(|pixel| pixel[0]).collect()
+}
+
+#[test]
+fn test_thumbnail_size() {
+    let data = fs::read("tests/rgb16.tiff").unwrap();
+    let image = encode_first(&data, &thumbnail(16, 100, ResampleFilter::Area));
+    assert_eq!((image.info.width, image.info.height), (16, 16));
+    assert_eq!(image.data.len(), 16 * 16 * 4);
+    let meta = image.info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["original_width"]), "64");
+
+    // Aspect ratio is kept, and images are never enlarged.
+    let data = make_mrc(8, 4, 1, 2, &f32_bytes(&[0.0; 32]));
+    let image = encode_first(&data, &thumbnail(4, 4, ResampleFilter::Lanczos));
+    assert_eq!((image.info.width, image.info.height), (4, 2));
+    let image = encode_first(&data, &thumbnail(100, 100, ResampleFilter::Lanczos));
+    assert_eq!((image.info.width, image.info.height), (8, 4));
+}
+
+#[test]
+fn test_area_average() {
+    let data = make_mrc(
+        4,
+        2,
+        1,
+        2,
+        &f32_bytes(&[0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0]),
+    );
+    let image = encode_first(&data, &thumbnail(2, 1, ResampleFilter::Area));
+    assert_eq!(red(&image), [191, 128]);
+}
+
+#[test]
+fn test_lanczos_keeps_flat_areas() {
+    let values: Vec<f32> = (0..64).map(|i| if i < 32 { 0.0 } else { 1.0 }).collect();
+    let data = make_mrc(8, 8, 1, 2, &f32_bytes(&values));
+    let image = encode_first(&data, &thumbnail(4, 4, ResampleFilter::Lanczos));
+    let red = red(&image);
+    assert_eq!(&red[..4], [0; 4]);
+    assert_eq!(&red[12..], [255; 4]);
+}
+
+#[test]
+fn test_thumbnail_pixel_size() {
+    let data = fs::read("tests/EMD-3197.mrc").unwrap();
+    let full = encode_first(&data, &DecodeOptions::default());
+    let options = DecodeOptions {
+        output: OutputFormat::Png,
+        ..thumbnail(full.info.width / 2, full.info.height, ResampleFilter::Area)
+    };
+    let image = encode_first(&data, &options);
+    let size = |image: &obscura_image::typ::Image| -> f64 {
+        format!("{}", image.info.metadata.as_ref().unwrap()["pixel_size_x"])
+            .parse()
+            .unwrap()
+    };
+    assert!((size(&image) - 2.0 * size(&full)).abs() < 1e-6);
+}
+
+#[test]
+fn test_thumbnail_refuses_mrc() {
+    let data = make_mrc(4, 4, 1, 2, &f32_bytes(&[1.0; 16]));
+    let options = DecodeOptions {
+        output: OutputFormat::Mrc,
+        ..thumbnail(2, 2, ResampleFilter::Area)
+    };
+    let Err(error) = common::encode(&data, &options) else {
+        panic!("Expected a thumbnail written as MRC to be refused");
+    };
+    assert!(format!("{error}").contains("thumbnail"));
+}
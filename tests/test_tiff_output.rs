@@ -0,0 +1,109 @@
+mod common;
+
+use common::{f32_bytes, i16_bytes, make_mrc, output_options};
+use obscura_image::options::{DecodeOptions, OutputFormat, TiffCompression, TiffOptions};
+use std::fs;
+use std::io::Cursor;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+fn tiff_output(tiff: TiffOptions) -> DecodeOptions {
+    DecodeOptions {
+        tiff,
+        ..output_options(OutputFormat::Tiff)
+    }
+}
+
+/// The next image of `decoder`, in a comparable form.
+fn read_image<R: std::io::Read + std::io::Seek>(decoder: &mut Decoder<R>) -> String {
+    format!("{:?}", decoder.read_image().unwrap())
+}
+
+fn encode(data: &[u8], options: &DecodeOptions) -> obscura_image::typ::Output {
+    common::encode(data, options).unwrap()
+}
+
+#[test]
+fn test_tiff_from_float_mrc() {
+    let values = [-1.5, 0.0, 0.25, 1e6, 3.0, -7.0];
+    let data = make_mrc(3, 2, 1, 2, &f32_bytes(&values));
+    let output = encode(&data, &tiff_output(TiffOptions::default()));
+    assert_eq!(format!("{}", output.encoding["compression"]), "lzw");
+    let image = &output.images[0];
+    assert_eq!(image.format, OutputFormat::Tiff);
+    assert!(image.samples.is_none());
+
+    let mut decoder = Decoder::new(Cursor::new(&image.data)).unwrap();
+    assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+    assert_eq!(
+        read_image(&mut decoder),
+        format!("{:?}", DecodingResult::F32(values.to_vec()))
+    );
+    // 1 Å pixels
+    assert_eq!(decoder.get_tag_u32(Tag::ResolutionUnit).unwrap(), 3);
+    assert_eq!(
+        decoder.get_tag_u32_vec(Tag::XResolution).unwrap(),
+        [100_000_000, 1]
+    );
+}
+
+#[test]
+fn test_tiff_from_int16_mrc() {
+    let values = [-32768, 0, 1000, 32767];
+    let data = make_mrc(2, 2, 1, 1, &i16_bytes(&values));
+    let output = encode(&data, &tiff_output(TiffOptions::default()));
+    let mut decoder = Decoder::new(Cursor::new(&output.images[0].data)).unwrap();
+    assert_eq!(
+        read_image(&mut decoder),
+        format!("{:?}", DecodingResult::I16(values.to_vec()))
+    );
+}
+
+#[test]
+fn test_tiff_roundtrip_16bit() {
+    let data = fs::read("tests/rgb16.tiff").unwrap();
+    let options = DecodeOptions {
+        samples: true,
+        ..tiff_output(TiffOptions {
+            compression: TiffCompression::Deflate,
+            ..Default::default()
+        })
+    };
+    let output = encode(&data, &options);
+    assert!(output.images[0].samples.is_some());
+
+    let mut decoder = Decoder::new(Cursor::new(&output.images[0].data)).unwrap();
+    assert_eq!(decoder.get_tag_u32(Tag::Compression).unwrap(), 8);
+    let mut original = Decoder::new(Cursor::new(&data)).unwrap();
+    assert_eq!(read_image(&mut decoder), read_image(&mut original));
+}
+
+#[test]
+fn test_multipage_tiff() {
+    let data = fs::read("tests/multipage.tiff").unwrap();
+    let output = encode(
+        &data,
+        &tiff_output(TiffOptions {
+            multipage: true,
+            ..Default::default()
+        }),
+    );
+    assert_eq!(output.images.len(), 1);
+    let meta = output.images[0].info.metadata.as_ref().unwrap();
+    assert_eq!(format!("{}", meta["page_count"]), "2");
+
+    let mut decoder = Decoder::new(Cursor::new(&output.images[0].data)).unwrap();
+    let mut original = Decoder::new(Cursor::new(&data)).unwrap();
+    for page in 0..2 {
+        assert_eq!(
+            read_image(&mut decoder),
+            read_image(&mut original),
+            "page {page}"
+        );
+        assert_eq!(decoder.more_images(), page == 0);
+        if page == 0 {
+            decoder.next_image().unwrap();
+            original.next_image().unwrap();
+        }
+    }
+}
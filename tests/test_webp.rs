@@ -0,0 +1,48 @@
+mod common;
+
+use common::{encode_first, output_options};
+use obscura_image::options::OutputFormat;
+use std::fs;
+use std::io::Cursor;
+
+/// Decode `path` and encode its first image as `output`.
+fn encode_file(path: &str, output: OutputFormat) -> obscura_image::typ::Image {
+    encode_first(&fs::read(path).unwrap(), &output_options(output))
+}
+
+/// Read back a WebP as RGB(A) pixels.
+fn read_webp(data: &[u8]) -> (u32, u32, Vec<u8>) {
+    let mut decoder = image_webp::WebPDecoder::new(Cursor::new(data)).unwrap();
+    let (width, height) = decoder.dimensions();
+    let mut buffer = vec![0; decoder.output_buffer_size().unwrap()];
+    decoder.read_image(&mut buffer).unwrap();
+    (width, height, buffer)
+}
+
+#[test]
+fn test_webp_lossless() {
+    for path in ["tests/rgb8.tiff", "tests/gray8.tiff"] {
+        let webp = encode_file(path, OutputFormat::Webp);
+        assert_eq!(webp.format, OutputFormat::Webp);
+        assert_eq!(&webp.data[..4], b"RIFF");
+        assert_eq!(&webp.data[8..16], b"WEBPVP8L");
+
+        let rgba = encode_file(path, OutputFormat::Rgba);
+        let (width, height, pixels) = read_webp(&webp.data);
+        assert_eq!((width, height), (webp.info.width, webp.info.height));
+        let rgb: Vec<u8> = rgba
+            .data
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect();
+        assert_eq!(pixels, rgb, "{path}");
+    }
+}
+
+#[test]
+fn test_webp_16bit() {
+    let webp = encode_file("tests/rgb16.tiff", OutputFormat::Webp);
+    let (width, height, pixels) = read_webp(&webp.data);
+    assert_eq!((width, height), (64, 64));
+    assert_eq!(pixels.len(), 64 * 64 * 3);
+}